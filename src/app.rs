@@ -9,8 +9,10 @@ pub struct ModbusApp {
     sel_query_index: usize,
     device_templates: Vec<std::path::PathBuf>,
     query_templates: Vec<std::path::PathBuf>,
+    /// Polling always starts stopped, so opening the app sends nothing on its own
     #[serde(skip)]
-    timer : std::time::SystemTime,
+    polling: bool,
+    discovery: crate::discovery::Discovery,
 }
 
 impl Default for ModbusApp {
@@ -22,7 +24,8 @@ impl Default for ModbusApp {
             sel_query_index: usize::MAX,
             device_templates: vec![],
             query_templates: vec![],
-            timer : std::time::SystemTime::now(),
+            polling: false,
+            discovery: Default::default(),
         }
     }
 }
@@ -84,6 +87,15 @@ impl eframe::App for ModbusApp {
         // Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
         
+        for device in self.devices.iter_mut() {
+            device.process_replies();
+            if self.polling {
+                if let Some(wait) = device.poll_queries(ctx) {
+                    ctx.request_repaint_after(wait);
+                }
+            }
+        }
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
 
@@ -163,9 +175,24 @@ impl eframe::App for ModbusApp {
                             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                         }
                     });
+                    ui.menu_button("Tools", |ui| {
+                        if ui.button("Discover Devices").clicked() {
+                            self.discovery.open = true;
+                            ui.close_menu();
+                        }
+                    });
                     ui.add_space(16.0);
                 }
 
+                if self.polling {
+                    if ui.button("\u{23F9} Stop Polling").clicked() {
+                        self.polling = false;
+                    }
+                } else if ui.button("\u{23F5} Start Polling").clicked() {
+                    self.polling = true;
+                }
+                ui.add_space(16.0);
+
                 egui::widgets::global_dark_light_mode_buttons(ui);
             });
         });
@@ -182,11 +209,22 @@ impl eframe::App for ModbusApp {
                         true,
                     )
                     .show_header(ui, |ui| {
-                        if ui
+                        let tv = ui
                             .toggle_value(&mut x.selected, &x.lable)
                             .context_menu(|ui| {
                                 //if ui.button("\u{2B06} Move Up").clicked(){}
                                 //if ui.button("\u{2B07} Move Down").clicked(){}
+                                if x.in_flight() && ui.button("\u{23F9} Cancel Requests").clicked()
+                                {
+                                    x.cancel_requests();
+                                    ui.close_menu();
+                                }
+                                if x.connection_state() != crate::transport::LinkState::Disconnected
+                                    && ui.button("\u{1F50C} Disconnect").clicked()
+                                {
+                                    x.disconnect();
+                                    ui.close_menu();
+                                }
                                 if dev_index > 0 && ui.button("\u{1F5D1} Delete").clicked() {
                                     if self.sel_device_index == dev_index {
                                         self.sel_device_index -= 1;
@@ -194,12 +232,13 @@ impl eframe::App for ModbusApp {
                                     ui.close_menu();
                                     retain = false;
                                 }
-                            })
-                            .clicked()
-                        {
+                            });
+
+                        if tv.clicked() {
                             self.sel_device_index = dev_index;
                             self.sel_query_index = usize::MAX;
                         }
+                        x.draw_connection_state(ui);
                     })
                     .body(|ui| {
                         (self.sel_query_index, self.sel_device_index) = x.build_query_tree(
@@ -220,6 +259,13 @@ impl eframe::App for ModbusApp {
             });
         });
 
+        let found = self.discovery.draw_window(ctx);
+        if !found.is_empty() {
+            self.devices.extend(found);
+            self.sel_device_index = self.devices.len() - 1;
+            self.sel_query_index = usize::MAX;
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             self.devices[self.sel_device_index].draw_device_frame(ui, self.sel_query_index);
         });
@@ -0,0 +1,259 @@
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CapabilityAction {
+    Nothing,
+    Start,
+    Cancel,
+}
+
+/// What a device made of a function code
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub enum Support {
+    Supported,
+    /// Known to the device, but the request itself was refused
+    Exception(u8),
+    IllegalFunction,
+    Timeout,
+    Error(String),
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct CodeResult {
+    pub function_code: u8,
+    pub support: Support,
+}
+
+/// Function codes tried, in this order
+const PROBES: [(u8, &str); 18] = [
+    (1, "Read Coils"),
+    (2, "Read Discrete Inputs"),
+    (3, "Read Holding Registers"),
+    (4, "Read Input Registers"),
+    (5, "Write Single Coil"),
+    (6, "Write Single Register"),
+    (7, "Read Exception Status"),
+    (8, "Diagnostics"),
+    (11, "Get Comm Event Counter"),
+    (12, "Get Comm Event Log"),
+    (15, "Write Multiple Coils"),
+    (16, "Write Multiple Registers"),
+    (17, "Report Server ID"),
+    (20, "Read File Record"),
+    (22, "Mask Write Register"),
+    (23, "Read/Write Multiple Registers"),
+    (24, "Read FIFO Queue"),
+    (43, "Read Device Identification"),
+];
+
+/// Finds which function codes a device supports, with requests that leave it
+/// as it is. Writes ask for something invalid, so a device that knows them
+/// answers ILLEGAL DATA VALUE where one that doesn't answers ILLEGAL FUNCTION.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
+pub struct CapabilityProbe {
+    pub reg: u16,
+    /// FC6 and FC22 have no invalid form, they are only tried when asked to
+    /// write the register back as it is
+    pub write_back: bool,
+    /// Kept with the device, queries warn about codes it rejected
+    pub results: Vec<CodeResult>,
+    #[serde(skip)]
+    next: Option<usize>,
+    #[serde(skip)]
+    pending: Option<(u64, u8)>,
+    #[serde(skip)]
+    read_value: Option<u16>,
+    #[serde(skip)]
+    unit_id: u8,
+    #[serde(skip)]
+    error: String,
+}
+
+impl CapabilityProbe {
+    pub fn running(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// The device answered this function code with ILLEGAL FUNCTION
+    pub fn rejects(&self, function_code: u8) -> bool {
+        self.results.iter().any(|x| {
+            x.function_code == function_code && matches!(x.support, Support::IllegalFunction)
+        })
+    }
+
+    pub fn start(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+        unit_id: u8,
+    ) {
+        self.cancel(worker);
+        self.results.clear();
+        self.read_value = None;
+        self.error.clear();
+        self.unit_id = unit_id;
+        // Nobody answers a broadcast, every code would look like a timeout
+        if unit_id == 0 && settings.unit_0_is_broadcast() {
+            self.error = "Unit 0 is broadcast on this link, probe a slave's own unit ID".to_owned();
+            return;
+        }
+        self.next = Some(0);
+        self.send_next(worker, settings);
+    }
+
+    pub fn cancel(&mut self, worker: &crate::worker::Worker) {
+        if let Some((id, _)) = self.pending.take() {
+            worker.cancel(id);
+        }
+        self.next = None;
+    }
+
+    pub fn waits_for(&self, reply: &crate::worker::Reply) -> bool {
+        matches!(self.pending, Some((id, _)) if id == reply.id)
+    }
+
+    pub fn receive(
+        &mut self,
+        reply: crate::worker::Reply,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+    ) {
+        let Some((_, function_code)) = self.pending.take() else {
+            return;
+        };
+        let proto = settings.transport.proto();
+        let support = match reply.response {
+            Ok(response) => match crate::transport::exception_code(&response, proto) {
+                Some(0x01) => Support::IllegalFunction,
+                Some(code) => Support::Exception(code),
+                None => {
+                    if let [3, 2, hi, lo] = crate::transport::response_pdu(&response, proto) {
+                        self.read_value = Some(u16::from_be_bytes([*hi, *lo]));
+                    }
+                    Support::Supported
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => Support::Timeout,
+            Err(e) => Support::Error(e.to_string()),
+        };
+        self.results.push(CodeResult {
+            function_code,
+            support,
+        });
+        self.send_next(worker, settings);
+    }
+
+    /// Request for one function code, `None` when there is no harmless one
+    fn pdu(&self, function_code: u8) -> Option<Vec<u8>> {
+        let [hi, lo] = self.reg.to_be_bytes();
+        Some(match function_code {
+            1..=4 => vec![function_code, hi, lo, 0, 1],
+            // Neither ON nor OFF
+            5 => vec![5, hi, lo, 0x12, 0x34],
+            6 if self.write_back => {
+                let [value_hi, value_lo] = self.read_value?.to_be_bytes();
+                vec![6, hi, lo, value_hi, value_lo]
+            }
+            7 | 11 | 12 | 17 => vec![function_code],
+            // Return Query Data, the device echoes it
+            8 => vec![8, 0, 0, 0xA5, 0x37],
+            // Nothing to write
+            15 | 16 => vec![function_code, hi, lo, 0, 0, 0],
+            // One register of record 0 of file 1
+            20 => vec![20, 7, 6, 0, 1, 0, 0, 0, 1],
+            // AND with all ones, OR with nothing. Still a read-modify-write
+            // that can undo a write of another master in between.
+            22 if self.write_back => vec![22, hi, lo, 0xFF, 0xFF, 0, 0],
+            // Read one, write none
+            23 => vec![23, hi, lo, 0, 1, hi, lo, 0, 0, 0],
+            24 => vec![24, hi, lo],
+            // Basic identification from the first object on
+            43 => vec![43, 14, 1, 0],
+            _ => return None,
+        })
+    }
+
+    fn send_next(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+    ) {
+        while let Some(index) = self.next {
+            self.next = (index + 1 < PROBES.len()).then_some(index + 1);
+
+            let function_code = PROBES[index].0;
+            if let Some(pdu) = self.pdu(function_code) {
+                let request =
+                    crate::transport::frame_request(self.unit_id, &pdu, settings.transport.proto());
+                let id = worker.submit(settings.clone(), request);
+                self.pending = Some((id, function_code));
+                return;
+            }
+        }
+    }
+
+    pub fn draw_capability_probe(&mut self, ui: &mut egui::Ui) -> CapabilityAction {
+        let mut action = CapabilityAction::Nothing;
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Offset:"));
+            ui.add(
+                egui::DragValue::new(&mut self.reg)
+                    .clamp_range(0..=u16::MAX)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+            ui.checkbox(&mut self.write_back, "FC6/FC22").on_hover_text(
+                "Write the value FC3 read back to the same register, \
+                and mask it leaving every bit as it is",
+            );
+
+            if self.running() {
+                if ui.button("\u{23F9} Cancel").clicked() {
+                    action = CapabilityAction::Cancel;
+                }
+            } else if ui.button("\u{23F5} Probe").clicked() {
+                action = CapabilityAction::Start;
+            }
+        });
+
+        if self.running() {
+            ui.add(
+                egui::ProgressBar::new(self.results.len() as f32 / PROBES.len() as f32).text(
+                    format!("{} of {} function codes", self.results.len(), PROBES.len()),
+                ),
+            );
+        }
+
+        if !self.error.is_empty() {
+            ui.label(self.error.as_str());
+        }
+
+        for x in self.results.iter() {
+            let name = PROBES
+                .iter()
+                .find(|(code, _)| *code == x.function_code)
+                .map_or("", |(_, name)| name);
+            ui.horizontal(|ui| {
+                ui.add_sized(
+                    [200.0, 10.0],
+                    egui::Label::new(format!("FC{} {}", x.function_code, name)),
+                );
+                match &x.support {
+                    Support::Supported => ui.label("Supported"),
+                    Support::Exception(code) => ui.label(format!(
+                        "Exception {:02X}, {}",
+                        code,
+                        crate::transport::exception_text(*code)
+                    )),
+                    Support::IllegalFunction => {
+                        ui.colored_label(ui.visuals().warn_fg_color, "Illegal function")
+                    }
+                    Support::Timeout => ui.label("Timed out"),
+                    Support::Error(e) => ui.label(e.as_str()),
+                };
+            });
+        }
+
+        action
+    }
+}
@@ -6,16 +6,49 @@ pub enum ListItemAction {
     MoveDown,
 }
 
+/// A slave reached through this device, like an RS-485 unit behind a TCP gateway
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+pub struct Slave {
+    pub lable: String,
+    pub unit_id: u8,
+}
+
+impl Default for Slave {
+    fn default() -> Self {
+        Self {
+            lable: "New Slave".to_owned(),
+            unit_id: 1,
+        }
+    }
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct ModbusDevice {
     pub lable: String,
     pub unit_id: u8,
+    /// Slaves sharing this device's connection, queries can address them by name
+    pub slaves: Vec<Slave>,
+    pub transport: crate::transport::Transport,
     pub ip: String,
     pub port: String,
+    pub serial: crate::transport::SerialSettings,
+    pub tls: crate::tls::TlsSettings,
+    pub timing: crate::transport::TimingSettings,
+    /// Modbus/TCP gateway to a serial line, unit 0 is a broadcast through it
+    pub gateway: bool,
     pub selected: bool,
     pub querys: Vec<crate::query::QueryWrapper>,
     pub notes: String,
+    pub scan: crate::scan::UnitScan,
+    pub register_probe: crate::regmap::RegisterProbe,
+    pub capabilities: crate::capability::CapabilityProbe,
+    /// Kept with the device, so templates tell which firmware they were made with
+    pub identity: crate::identity::DeviceIdentity,
+    /// Started on the first request, so loading a device opens nothing
+    #[serde(skip)]
+    worker: Option<crate::worker::Worker>,
 }
 
 impl Default for ModbusDevice {
@@ -23,11 +56,22 @@ impl Default for ModbusDevice {
         Self {
             lable: "New Device".to_owned(),
             unit_id: 1,
+            slaves: vec![],
+            transport: crate::transport::Transport::Tcp,
             ip: Default::default(),
             port: Default::default(),
+            serial: Default::default(),
+            tls: Default::default(),
+            timing: Default::default(),
+            gateway: false,
             selected: false,
             querys: vec![crate::query::QueryWrapper::new()],
             notes: "Add device notes here".to_string(),
+            scan: Default::default(),
+            register_probe: Default::default(),
+            capabilities: Default::default(),
+            identity: Default::default(),
+            worker: None,
         }
     }
 }
@@ -37,11 +81,148 @@ impl ModbusDevice {
         Self {
             lable: "New Device".to_owned(),
             unit_id: 1,
+            slaves: vec![],
+            transport: crate::transport::Transport::Tcp,
             ip: Default::default(),
             port: Default::default(),
+            serial: Default::default(),
+            tls: Default::default(),
+            timing: Default::default(),
+            gateway: false,
             selected: false,
             querys: Default::default(),
             notes: "Add device notes here".to_string(),
+            scan: Default::default(),
+            register_probe: Default::default(),
+            capabilities: Default::default(),
+            identity: Default::default(),
+            worker: None,
+        }
+    }
+
+    pub fn link_settings(&self) -> crate::transport::LinkSettings {
+        crate::transport::LinkSettings {
+            transport: self.transport,
+            ip: self.ip.clone(),
+            port: self.port.clone(),
+            serial: self.serial.clone(),
+            tls: self.tls.clone(),
+            timing: self.timing.clone(),
+            gateway: self.gateway,
+        }
+    }
+
+    pub fn connection_state(&self) -> crate::transport::LinkState {
+        match &self.worker {
+            Some(worker) => worker.status().state,
+            None => crate::transport::LinkState::Disconnected,
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        if let Some(worker) = &self.worker {
+            worker.close();
+        }
+    }
+
+    /// Sends the queries that are due for polling, returns the time until the next one is
+    pub fn poll_queries(&mut self, ctx: &egui::Context) -> Option<std::time::Duration> {
+        if !self.querys.iter().any(|x| x.poll_enabled) {
+            return None;
+        }
+        let settings = self.link_settings();
+        let worker = self
+            .worker
+            .get_or_insert_with(|| crate::worker::Worker::start(ctx.clone()));
+        let now = std::time::Instant::now();
+        self.querys
+            .iter_mut()
+            .filter_map(|x| x.poll(worker, &settings, self.unit_id, now))
+            .min()
+    }
+
+    pub fn in_flight(&self) -> bool {
+        self.querys.iter().any(|x| x.in_flight())
+    }
+
+    pub fn cancel_requests(&mut self) {
+        if let Some(worker) = &self.worker {
+            for x in self.querys.iter_mut() {
+                x.cancel(worker);
+            }
+        }
+    }
+
+    /// Hands finished requests back to the queries that sent them
+    pub fn process_replies(&mut self) {
+        // A running scan sends its next probe as soon as one is answered
+        let settings = (self.scan.running()
+            || self.register_probe.running()
+            || self.capabilities.running()
+            || self.identity.running())
+        .then(|| self.link_settings());
+        if let Some(worker) = &mut self.worker {
+            for reply in worker.poll() {
+                if self.scan.waits_for(&reply) {
+                    self.scan.receive(reply, worker, settings.as_ref().unwrap());
+                } else if self.register_probe.waits_for(&reply) {
+                    self.register_probe
+                        .receive(reply, worker, settings.as_ref().unwrap());
+                } else if self.capabilities.waits_for(&reply) {
+                    self.capabilities
+                        .receive(reply, worker, settings.as_ref().unwrap());
+                } else if self.identity.waits_for(&reply) {
+                    self.identity
+                        .receive(reply, worker, settings.as_ref().unwrap());
+                } else if let Some(x) = self.querys.iter_mut().find(|x| x.waits_for(&reply)) {
+                    x.receive(reply);
+                }
+            }
+        }
+    }
+
+    /// Status light next to the device in the tree
+    pub fn draw_connection_state(&mut self, ui: &mut egui::Ui) {
+        let status = match &self.worker {
+            Some(worker) => worker.status().clone(),
+            None => Default::default(),
+        };
+        if let Some(wait) = status.reconnect_in() {
+            ui.ctx().request_repaint_after(wait);
+        }
+
+        let color = match status.state {
+            crate::transport::LinkState::Disconnected => ui.visuals().weak_text_color(),
+            crate::transport::LinkState::Connected => egui::Color32::GREEN,
+            crate::transport::LinkState::Reconnecting => egui::Color32::YELLOW,
+            crate::transport::LinkState::Failed => egui::Color32::RED,
+        };
+        let identity = self.identity.summary();
+        ui.colored_label(color, "\u{25CF}")
+            .on_hover_text(match identity.is_empty() {
+                true => status.status_text(),
+                false => format!("{}\n{}", status.status_text(), identity),
+            });
+    }
+
+    /// Gives every unit found by the scan a slave entry and a query of its own
+    fn add_live_units(&mut self) {
+        for unit_id in self.scan.live_units() {
+            if self.slaves.iter().any(|x| x.unit_id == unit_id) {
+                continue;
+            }
+            self.slaves.push(Slave {
+                lable: format!("Unit {}", unit_id),
+                unit_id,
+            });
+
+            let mut query = crate::query::QueryWrapper::new();
+            query.lable = format!("Unit {}", unit_id);
+            query.function_code = self.scan.function_code;
+            query.reg = self.scan.reg;
+            query.override_unit_id = true;
+            query.unit_id = unit_id;
+            self.querys.push(query);
         }
     }
 
@@ -57,20 +238,79 @@ impl ModbusDevice {
             });
 
             ui.horizontal(|ui| {
-                ui.add_sized([100.0, 10.0], egui::Label::new("Ip Adress:"));
-                ui.add_sized(
-                    [200.0, 10.0],
-                    egui::TextEdit::singleline(&mut self.ip).hint_text("Input IP"),
-                );
+                ui.add_sized([100.0, 10.0], egui::Label::new("Transport:"));
+                egui::ComboBox::from_id_source("Transport")
+                    .selected_text(format!("{:?}", self.transport))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut self.transport,
+                            crate::transport::Transport::Tcp,
+                            "Modbus TCP",
+                        );
+                        ui.selectable_value(
+                            &mut self.transport,
+                            crate::transport::Transport::Udp,
+                            "Modbus UDP",
+                        );
+                        ui.selectable_value(
+                            &mut self.transport,
+                            crate::transport::Transport::RtuSerial,
+                            "Modbus RTU over serial port",
+                        );
+                        ui.selectable_value(
+                            &mut self.transport,
+                            crate::transport::Transport::RtuOverTcp,
+                            "Modbus RTU over TCP",
+                        );
+                        ui.selectable_value(
+                            &mut self.transport,
+                            crate::transport::Transport::AsciiSerial,
+                            "Modbus ASCII over serial port",
+                        );
+                        ui.selectable_value(
+                            &mut self.transport,
+                            crate::transport::Transport::AsciiOverTcp,
+                            "Modbus ASCII over TCP",
+                        );
+                        ui.selectable_value(
+                            &mut self.transport,
+                            crate::transport::Transport::Tls,
+                            "Modbus/TCP Security (TLS)",
+                        );
+                    });
             });
 
-            ui.horizontal(|ui| {
-                ui.add_sized([100.0, 10.0], egui::Label::new("TCP/UDP Port:"));
-                ui.add_sized(
-                    [200.0, 10.0],
-                    egui::TextEdit::singleline(&mut self.port).hint_text("Input Port"),
-                );
-            });
+            match self.transport {
+                crate::transport::Transport::Tcp
+                | crate::transport::Transport::Udp
+                | crate::transport::Transport::RtuOverTcp
+                | crate::transport::Transport::AsciiOverTcp
+                | crate::transport::Transport::Tls => {
+                    ui.horizontal(|ui| {
+                        ui.add_sized([100.0, 10.0], egui::Label::new("Ip Adress:"));
+                        ui.add_sized(
+                            [200.0, 10.0],
+                            egui::TextEdit::singleline(&mut self.ip).hint_text("Input IP"),
+                        );
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.add_sized([100.0, 10.0], egui::Label::new("TCP/UDP Port:"));
+                        ui.add_sized(
+                            [200.0, 10.0],
+                            egui::TextEdit::singleline(&mut self.port).hint_text("Input Port"),
+                        );
+                    });
+
+                    if self.transport == crate::transport::Transport::Tls {
+                        self.tls.draw_tls_settings(ui);
+                    }
+                }
+                crate::transport::Transport::RtuSerial
+                | crate::transport::Transport::AsciiSerial => self.serial.draw_serial_settings(ui),
+            }
+
+            self.timing.draw_timing_settings(ui);
 
             ui.horizontal(|ui| {
                 ui.add_sized([100.0, 10.0], egui::Label::new("Device ID:"));
@@ -80,6 +320,112 @@ impl ModbusDevice {
                         .speed(0.0),
                 )
                 .on_hover_cursor(egui::CursorIcon::Text);
+                if self.transport.proto() == rmodbus::ModbusProto::TcpUdp {
+                    ui.checkbox(&mut self.gateway, "Gateway").on_hover_text(
+                        "Hands unit 0 on to its serial line as a broadcast, instead of answering it",
+                    );
+                }
+            });
+
+            egui::CollapsingHeader::new("Identification").show(ui, |ui| {
+                match self.identity.draw_identity(ui) {
+                    crate::identity::IdentityAction::Read => {
+                        let settings = self.link_settings();
+                        let worker = self
+                            .worker
+                            .get_or_insert_with(|| crate::worker::Worker::start(ui.ctx().clone()));
+                        self.identity.read(worker, &settings, self.unit_id);
+                    }
+                    crate::identity::IdentityAction::Cancel => {
+                        if let Some(worker) = &self.worker {
+                            self.identity.cancel(worker);
+                        }
+                    }
+                    crate::identity::IdentityAction::Nothing => (),
+                }
+            });
+
+            egui::CollapsingHeader::new(format!("Slaves ({})", self.slaves.len())).show(ui, |ui| {
+                self.slaves.retain_mut(|x| {
+                    let mut retain = true;
+                    ui.horizontal(|ui| {
+                        ui.add_sized([150.0, 10.0], egui::TextEdit::singleline(&mut x.lable));
+                        ui.add(
+                            egui::DragValue::new(&mut x.unit_id)
+                                .clamp_range(1..=247)
+                                .speed(0.0),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text);
+                        if ui.button("\u{1F5D1}").clicked() {
+                            retain = false;
+                        }
+                    });
+                    retain
+                });
+                if ui.button("Add Slave").clicked() {
+                    self.slaves.push(Slave::default());
+                }
+            });
+
+            egui::CollapsingHeader::new("Unit Scan").show(ui, |ui| {
+                match self.scan.draw_unit_scan(ui) {
+                    crate::scan::ScanAction::Start => {
+                        let settings = self.link_settings();
+                        let worker = self
+                            .worker
+                            .get_or_insert_with(|| crate::worker::Worker::start(ui.ctx().clone()));
+                        self.scan.start(worker, &settings);
+                    }
+                    crate::scan::ScanAction::Cancel => {
+                        if let Some(worker) = &self.worker {
+                            self.scan.cancel(worker);
+                        }
+                    }
+                    crate::scan::ScanAction::AddLiveUnits => self.add_live_units(),
+                    crate::scan::ScanAction::Nothing => (),
+                }
+            });
+
+            egui::CollapsingHeader::new("Function Codes").show(ui, |ui| {
+                match self.capabilities.draw_capability_probe(ui) {
+                    crate::capability::CapabilityAction::Start => {
+                        let settings = self.link_settings();
+                        let worker = self
+                            .worker
+                            .get_or_insert_with(|| crate::worker::Worker::start(ui.ctx().clone()));
+                        self.capabilities.start(worker, &settings, self.unit_id);
+                    }
+                    crate::capability::CapabilityAction::Cancel => {
+                        if let Some(worker) = &self.worker {
+                            self.capabilities.cancel(worker);
+                        }
+                    }
+                    crate::capability::CapabilityAction::Nothing => (),
+                }
+            });
+
+            egui::CollapsingHeader::new("Register Map Probe").show(ui, |ui| {
+                match self
+                    .register_probe
+                    .draw_register_probe(ui, self.transport.proto())
+                {
+                    crate::regmap::ProbeAction::Start => {
+                        let settings = self.link_settings();
+                        let worker = self
+                            .worker
+                            .get_or_insert_with(|| crate::worker::Worker::start(ui.ctx().clone()));
+                        self.register_probe.start(worker, &settings, self.unit_id);
+                    }
+                    crate::regmap::ProbeAction::Cancel => {
+                        if let Some(worker) = &self.worker {
+                            self.register_probe.cancel(worker);
+                        }
+                    }
+                    crate::regmap::ProbeAction::CreateQueries => self
+                        .querys
+                        .extend(self.register_probe.queries(self.transport.proto())),
+                    crate::regmap::ProbeAction::Nothing => (),
+                }
             });
 
             ui.add_sized(
@@ -87,7 +433,13 @@ impl ModbusDevice {
                 egui::TextEdit::multiline(&mut self.notes),
             );
         } else {
-            self.querys.get_mut(query_id).unwrap().draw_query_frame(ui);
+            self.querys.get_mut(query_id).unwrap().draw_query_frame(
+                ui,
+                self.unit_id,
+                &self.slaves,
+                &self.capabilities,
+                self.transport.proto(),
+            );
         }
     }
 
@@ -102,6 +454,7 @@ impl ModbusDevice {
         let mut final_index = 0;
         let mut ret: bool = false;
         let mut retain = true;
+        let settings = self.link_settings();
         self.querys.retain_mut(|x| {
             egui::collapsing_header::CollapsingState::load_with_default_open(
                 ui.ctx(),
@@ -109,8 +462,13 @@ impl ModbusDevice {
                 true,
             )
             .show_header(ui, |ui| {
+                let unit_id = x.addressed_unit(self.unit_id);
                 let tv = ui
                     .toggle_value(&mut x.selected, &x.lable)
+                    .on_hover_text(match self.slaves.iter().find(|s| s.unit_id == unit_id) {
+                        Some(slave) => format!("Unit {}, {}", unit_id, slave.lable),
+                        None => format!("Unit {}", unit_id),
+                    })
                     .context_menu(|ui| {
                         if quer_index > 0 && ui.button("\u{1F5D1} Delete").clicked() {
                             if index == quer_index {
@@ -127,8 +485,18 @@ impl ModbusDevice {
                 }
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::RIGHT), |ui| {
-                    if ui.button("\u{23F5}").clicked() {
-                        x.execute(&self.ip, &self.port)
+                    if x.in_flight() {
+                        if ui.button("\u{23F9}").on_hover_text("Cancel").clicked() {
+                            if let Some(worker) = &self.worker {
+                                x.cancel(worker);
+                            }
+                        }
+                        ui.spinner();
+                    } else if ui.button("\u{23F5}").clicked() {
+                        let worker = self
+                            .worker
+                            .get_or_insert_with(|| crate::worker::Worker::start(ui.ctx().clone()));
+                        x.execute(worker, &settings, self.unit_id)
                     }
                 });
 
@@ -145,7 +513,9 @@ impl ModbusDevice {
                 }
             })
             .body(|ui| {
+                let mut n = 0;
                 x.watched_list.retain_mut(|x| {
+                    n += 1;
                     let mut retain = true;
                     ui.horizontal(|ui| {
                         if !x.locked {
@@ -157,16 +527,13 @@ impl ModbusDevice {
                                     }
                                 });
 
-                            ui.add_sized(
-                                [60., 10.],
-                                egui::Label::new(x.resulting_value.to_string()),
-                            )
-                            .context_menu(|ui| {
-                                if ui.button("\u{1F5D1} Delete").clicked() {
-                                    ui.close_menu();
-                                    retain = false;
-                                }
-                            });
+                            ui.add_sized([60., 10.], egui::Label::new(x.value_text()))
+                                .context_menu(|ui| {
+                                    if ui.button("\u{1F5D1} Delete").clicked() {
+                                        ui.close_menu();
+                                        retain = false;
+                                    }
+                                });
 
                             ui.add_sized([50., 10.], egui::TextEdit::singleline(&mut x.suffix))
                                 .context_menu(|ui| {
@@ -175,24 +542,40 @@ impl ModbusDevice {
                                         retain = false;
                                     }
                                 });
+                            if x.data_type != crate::query::DataView::Text {
+                                egui::ComboBox::from_id_source((
+                                    "Watched endianness",
+                                    quer_index,
+                                    n,
+                                ))
+                                .selected_text(x.endianness.name())
+                                .width(60.)
+                                .show_ui(ui, |ui| {
+                                    for order in crate::query::Endianness::ALL {
+                                        ui.selectable_value(&mut x.endianness, order, order.name());
+                                    }
+                                });
+                            }
+                            ui.menu_button("\u{2630}", |ui| x.draw_mapping(ui))
+                                .response
+                                .on_hover_text("Named states and bits");
                             if ui.button("\u{1F512}").clicked() {
                                 x.locked = true
                             }
                         } else {
-                            ui.label(format!(
-                                "{}:     {} {}",
-                                x.label, x.resulting_value, x.suffix
-                            ))
-                            .on_hover_text(format!(
-                                "Factor {}   Offsett {}",
-                                x.factor, x.value_offsett
-                            ))
-                            .context_menu(|ui| {
-                                if ui.button("\u{1F511} Unlock").clicked() {
-                                    ui.close_menu();
-                                    x.locked = false;
-                                }
-                            });
+                            ui.label(format!("{}:     {} {}", x.label, x.value_text(), x.suffix))
+                                .on_hover_text(format!(
+                                    "Factor {}   Offsett {}   {}",
+                                    x.factor,
+                                    x.value_offsett,
+                                    x.endianness.name()
+                                ))
+                                .context_menu(|ui| {
+                                    if ui.button("\u{1F511} Unlock").clicked() {
+                                        ui.close_menu();
+                                        x.locked = false;
+                                    }
+                                });
                         }
                     });
 
@@ -0,0 +1,351 @@
+use crate::query::FC;
+
+/// Sub-functions of FC8 Diagnostics
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Clone, Copy)]
+pub enum SubFunction {
+    ReturnQueryData = 0x00,
+    RestartCommunications = 0x01,
+    ReturnDiagnosticRegister = 0x02,
+    ForceListenOnlyMode = 0x04,
+    ClearCounters = 0x0A,
+    BusMessageCount = 0x0B,
+    BusCommunicationErrorCount = 0x0C,
+    BusExceptionErrorCount = 0x0D,
+    ServerMessageCount = 0x0E,
+    ServerNoResponseCount = 0x0F,
+    ServerNakCount = 0x10,
+    ServerBusyCount = 0x11,
+    BusCharacterOverrunCount = 0x12,
+    ClearOverrunCounter = 0x14,
+}
+
+impl SubFunction {
+    const ALL: [SubFunction; 14] = [
+        SubFunction::ReturnQueryData,
+        SubFunction::RestartCommunications,
+        SubFunction::ReturnDiagnosticRegister,
+        SubFunction::ForceListenOnlyMode,
+        SubFunction::ClearCounters,
+        SubFunction::BusMessageCount,
+        SubFunction::BusCommunicationErrorCount,
+        SubFunction::BusExceptionErrorCount,
+        SubFunction::ServerMessageCount,
+        SubFunction::ServerNoResponseCount,
+        SubFunction::ServerNakCount,
+        SubFunction::ServerBusyCount,
+        SubFunction::BusCharacterOverrunCount,
+        SubFunction::ClearOverrunCounter,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SubFunction::ReturnQueryData => "Return Query Data",
+            SubFunction::RestartCommunications => "Restart Communications",
+            SubFunction::ReturnDiagnosticRegister => "Return Diagnostic Register",
+            SubFunction::ForceListenOnlyMode => "Force Listen Only Mode",
+            SubFunction::ClearCounters => "Clear Counters and Diagnostic Register",
+            SubFunction::BusMessageCount => "Return Bus Message Count",
+            SubFunction::BusCommunicationErrorCount => "Return Bus Communication Error Count",
+            SubFunction::BusExceptionErrorCount => "Return Bus Exception Error Count",
+            SubFunction::ServerMessageCount => "Return Server Message Count",
+            SubFunction::ServerNoResponseCount => "Return Server No Response Count",
+            SubFunction::ServerNakCount => "Return Server NAK Count",
+            SubFunction::ServerBusyCount => "Return Server Busy Count",
+            SubFunction::BusCharacterOverrunCount => "Return Bus Character Overrun Count",
+            SubFunction::ClearOverrunCounter => "Clear Overrun Counter and Flag",
+        }
+    }
+
+    /// Data sent along, the rest of the sub-functions send 0000
+    fn takes_data(&self) -> bool {
+        matches!(
+            self,
+            SubFunction::ReturnQueryData | SubFunction::RestartCommunications
+        )
+    }
+}
+
+/// Request PDU of a diagnostic function code
+pub fn request_pdu(function_code: FC, sub_function: SubFunction, data: u16) -> Vec<u8> {
+    match function_code {
+        FC::Diagnostics => {
+            let data = match sub_function.takes_data() {
+                true => data,
+                false => 0,
+            };
+            let mut pdu = vec![8];
+            pdu.extend_from_slice(&(sub_function as u16).to_be_bytes());
+            pdu.extend_from_slice(&data.to_be_bytes());
+            pdu
+        }
+        _ => vec![function_code as u8],
+    }
+}
+
+/// Response PDU as named fields, `None` if it is too short for its function code
+pub fn decode(pdu: &[u8]) -> Option<Vec<(String, String)>> {
+    let word =
+        |i: usize| -> Option<u16> { Some(u16::from_be_bytes([*pdu.get(i)?, *pdu.get(i + 1)?])) };
+    let mut fields = vec![];
+    match pdu.first()? {
+        7 => {
+            let status = *pdu.get(1)?;
+            fields.push(("Exception Status".to_owned(), format!("{:08b}", status)));
+            for bit in 0..8 {
+                fields.push((
+                    format!("Output {}", bit),
+                    on_off(status & (1 << bit) != 0).to_owned(),
+                ));
+            }
+        }
+        8 => {
+            let sub_function = word(1)?;
+            let data = word(3)?;
+            let name = SubFunction::ALL
+                .iter()
+                .find(|x| **x as u16 == sub_function)
+                .map_or("Unknown", |x| x.name());
+            fields.push((
+                "Sub-function".to_owned(),
+                format!("{:04X} {}", sub_function, name),
+            ));
+            let value = match sub_function {
+                0x02 => format!("{:016b}", data),
+                0x0B..=0x12 => data.to_string(),
+                _ => format!("{:04X}", data),
+            };
+            fields.push(("Data".to_owned(), value));
+        }
+        11 => {
+            fields.push(("Status".to_owned(), busy_text(word(1)?).to_owned()));
+            fields.push(("Event Count".to_owned(), word(3)?.to_string()));
+        }
+        12 => {
+            let len = *pdu.get(1)? as usize;
+            fields.push(("Status".to_owned(), busy_text(word(2)?).to_owned()));
+            fields.push(("Event Count".to_owned(), word(4)?.to_string()));
+            fields.push(("Message Count".to_owned(), word(6)?.to_string()));
+            // Newest event first
+            for (i, event) in pdu.get(8..2 + len)?.iter().enumerate() {
+                fields.push((format!("Event {}", i), event_text(*event)));
+            }
+        }
+        17 => {
+            let len = *pdu.get(1)? as usize;
+            let data = pdu.get(2..2 + len)?;
+            // The specification leaves the length of the ID to the device, most use one byte
+            if let [id, run, additional @ ..] = data {
+                fields.push(("Server ID".to_owned(), format!("{:02X}", id)));
+                fields.push((
+                    "Run Indicator".to_owned(),
+                    match run {
+                        0x00 => "OFF".to_owned(),
+                        0xFF => "ON".to_owned(),
+                        x => format!("{:02X}", x),
+                    },
+                ));
+                fields.push(("Additional Data".to_owned(), bytes_text(additional)));
+            } else {
+                fields.push(("Server ID".to_owned(), bytes_text(data)));
+            }
+        }
+        _ => return None,
+    }
+    Some(fields)
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+fn busy_text(status: u16) -> &'static str {
+    match status {
+        0xFFFF => "Busy",
+        _ => "Ready",
+    }
+}
+
+/// Event log byte as the serial line specification defines it
+fn event_text(event: u8) -> String {
+    if event == 0x00 {
+        return "Communication restart".to_owned();
+    }
+    if event == 0x04 {
+        return "Entered listen only mode".to_owned();
+    }
+    let flags: &[(u8, &str)] = if event & 0x80 != 0 {
+        &[
+            (0x02, "communication error"),
+            (0x10, "character overrun"),
+            (0x20, "in listen only mode"),
+            (0x40, "broadcast"),
+        ]
+    } else if event & 0x40 != 0 {
+        &[
+            (0x01, "read exception"),
+            (0x02, "server abort exception"),
+            (0x04, "server busy exception"),
+            (0x08, "server program NAK exception"),
+            (0x10, "write timeout"),
+            (0x20, "in listen only mode"),
+        ]
+    } else {
+        return format!("Unknown {:02X}", event);
+    };
+    let what = flags
+        .iter()
+        .filter(|(bit, _)| event & bit != 0)
+        .map(|(_, text)| *text)
+        .collect::<Vec<_>>();
+    let kind = if event & 0x80 != 0 {
+        "Received"
+    } else {
+        "Sent"
+    };
+    match what.is_empty() {
+        true => kind.to_owned(),
+        false => format!("{}, {}", kind, what.join(", ")),
+    }
+}
+
+/// Text if it reads as text, hex bytes otherwise
+fn bytes_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.is_empty() && !text.chars().any(|c| c.is_control()) => text.to_owned(),
+        _ => bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Sub-function and data editor of FC8
+pub fn draw_diagnostics_request(ui: &mut egui::Ui, sub_function: &mut SubFunction, data: &mut u16) {
+    ui.horizontal(|ui| {
+        ui.add_sized([80.0, 10.0], egui::Label::new("Sub-function:"));
+        egui::ComboBox::from_id_source("Diagnostics Sub-function")
+            .selected_text(sub_function.name())
+            .show_ui(ui, |ui| {
+                for x in SubFunction::ALL {
+                    ui.selectable_value(sub_function, x, format!("{:02} {}", x as u16, x.name()));
+                }
+            });
+        if sub_function.takes_data() {
+            ui.label("Data:");
+            ui.add(
+                egui::DragValue::new(data)
+                    .clamp_range(u16::MIN..=u16::MAX)
+                    .hexadecimal(4, false, true)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text)
+            .on_hover_text(match sub_function {
+                SubFunction::RestartCommunications => "FF00 also clears the event log",
+                _ => "Echoed back by the device",
+            });
+        }
+        if *sub_function == SubFunction::ForceListenOnlyMode {
+            ui.colored_label(ui.visuals().warn_fg_color, "\u{26A0}")
+                .on_hover_text("The device stops answering until Restart Communications");
+        }
+    });
+}
+
+/// The decoded fields of the last response
+pub fn draw_diagnostics_panel(ui: &mut egui::Ui, pdu: &[u8]) {
+    if pdu.is_empty() {
+        return;
+    }
+    match decode(pdu) {
+        Some(fields) => {
+            egui::Grid::new("diagnostics_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (name, value) in fields {
+                        ui.label(name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+        }
+        None => {
+            ui.label(rmodbus::ErrorKind::FrameBroken.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(fields: &[(String, String)], name: &str) -> String {
+        fields.iter().find(|x| x.0 == name).unwrap().1.clone()
+    }
+
+    #[test]
+    fn request_pdu_only_sends_data_along_where_taken() {
+        assert_eq!(
+            request_pdu(FC::Diagnostics, SubFunction::ReturnQueryData, 0xA537),
+            [8, 0x00, 0x00, 0xA5, 0x37]
+        );
+        assert_eq!(
+            request_pdu(FC::Diagnostics, SubFunction::BusMessageCount, 0xA537),
+            [8, 0x00, 0x0B, 0x00, 0x00]
+        );
+        assert_eq!(
+            request_pdu(FC::ReadExceptionStatus, SubFunction::ReturnQueryData, 1),
+            [7]
+        );
+    }
+
+    #[test]
+    fn decode_exception_status() {
+        let fields = decode(&[7, 0b0000_0101]).unwrap();
+        assert_eq!(field(&fields, "Exception Status"), "00000101");
+        assert_eq!(field(&fields, "Output 0"), "ON");
+        assert_eq!(field(&fields, "Output 1"), "OFF");
+        assert_eq!(field(&fields, "Output 2"), "ON");
+    }
+
+    #[test]
+    fn decode_diagnostics_counter() {
+        let fields = decode(&[8, 0x00, 0x0B, 0x01, 0x2C]).unwrap();
+        assert_eq!(
+            field(&fields, "Sub-function"),
+            "000B Return Bus Message Count"
+        );
+        assert_eq!(field(&fields, "Data"), "300");
+    }
+
+    #[test]
+    fn decode_comm_event_log() {
+        // Ready, 3 events, 9 messages, then a sent read exception and a restart
+        let fields = decode(&[12, 8, 0x00, 0x00, 0x00, 0x03, 0x00, 0x09, 0x41, 0x00]).unwrap();
+        assert_eq!(field(&fields, "Status"), "Ready");
+        assert_eq!(field(&fields, "Event Count"), "3");
+        assert_eq!(field(&fields, "Message Count"), "9");
+        assert_eq!(field(&fields, "Event 0"), "Sent, read exception");
+        assert_eq!(field(&fields, "Event 1"), "Communication restart");
+    }
+
+    #[test]
+    fn decode_report_server_id() {
+        let fields = decode(&[17, 4, 0x2A, 0xFF, b'O', b'K']).unwrap();
+        assert_eq!(field(&fields, "Server ID"), "2A");
+        assert_eq!(field(&fields, "Run Indicator"), "ON");
+        assert_eq!(field(&fields, "Additional Data"), "OK");
+    }
+
+    #[test]
+    fn decode_rejects_short_responses() {
+        assert!(decode(&[8, 0x00, 0x0B, 0x01]).is_none());
+        assert!(decode(&[11, 0xFF, 0xFF]).is_none());
+        assert!(decode(&[12, 8, 0x00, 0x00]).is_none());
+        assert!(decode(&[3, 2, 0, 1]).is_none());
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rmodbus::client::ModbusRequest;
+use rmodbus::ModbusProto;
+
+/// More targets than this is most likely a typo in the prefix length
+const MAX_TARGETS: usize = 65536;
+
+pub struct DiscoveredHost {
+    pub ip: Ipv4Addr,
+    pub port: u16,
+    pub unit_id: u8,
+    /// Round trip of the probe that was answered
+    pub latency: Duration,
+    pub exception: Option<u8>,
+}
+
+struct Run {
+    hosts: Receiver<DiscoveredHost>,
+    cancel: Arc<AtomicBool>,
+    done: Arc<AtomicUsize>,
+    total: usize,
+}
+
+/// Looks for Modbus/TCP servers by reading holding register 0 from every
+/// address and port given, a handful of connections at a time.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
+pub struct Discovery {
+    /// CIDR blocks, ranges or single addresses, comma separated
+    pub hosts: String,
+    pub ports: String,
+    /// Tried in order until one answers
+    pub unit_ids: String,
+    pub concurrency: usize,
+    pub timeout_ms: u64,
+    #[serde(skip)]
+    pub open: bool,
+    #[serde(skip)]
+    results: Vec<DiscoveredHost>,
+    #[serde(skip)]
+    error: String,
+    #[serde(skip)]
+    run: Option<Run>,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self {
+            hosts: "192.168.1.0/24".to_owned(),
+            ports: "502".to_owned(),
+            unit_ids: "1, 255".to_owned(),
+            concurrency: 32,
+            timeout_ms: 300,
+            open: false,
+            results: vec![],
+            error: Default::default(),
+            run: None,
+        }
+    }
+}
+
+impl Discovery {
+    pub fn running(&self) -> bool {
+        self.run.is_some()
+    }
+
+    pub fn start(&mut self, ctx: &egui::Context) {
+        self.cancel();
+        self.results.clear();
+        self.error.clear();
+
+        let (hosts, ports, unit_ids) = match (
+            parse_hosts(&self.hosts),
+            parse_numbers(&self.ports, 1, u16::MAX as u32),
+            parse_numbers(&self.unit_ids, 1, 255),
+        ) {
+            (Ok(hosts), Ok(ports), Ok(unit_ids)) => (hosts, ports, unit_ids),
+            (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+                self.error = e;
+                return;
+            }
+        };
+        let total = hosts.len() * ports.len();
+        if total > MAX_TARGETS {
+            self.error = format!("{} targets is more than the {} allowed", total, MAX_TARGETS);
+            return;
+        }
+
+        let targets: VecDeque<(Ipv4Addr, u16)> = hosts
+            .iter()
+            .flat_map(|ip| ports.iter().map(move |port| (*ip, *port as u16)))
+            .collect();
+        let targets = Arc::new(Mutex::new(targets));
+        let unit_ids: Arc<Vec<u8>> = Arc::new(unit_ids.iter().map(|x| *x as u8).collect());
+        let timeout = Duration::from_millis(self.timeout_ms.max(1));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let done = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = channel();
+
+        for _ in 0..self.concurrency.clamp(1, total.max(1)) {
+            let (targets, unit_ids, cancel, done, sender, ctx) = (
+                targets.clone(),
+                unit_ids.clone(),
+                cancel.clone(),
+                done.clone(),
+                sender.clone(),
+                ctx.clone(),
+            );
+            std::thread::spawn(move || {
+                while !cancel.load(Ordering::Relaxed) {
+                    let next = targets.lock().unwrap().pop_front();
+                    let Some((ip, port)) = next else {
+                        break;
+                    };
+                    if let Some(host) = probe(ip, port, &unit_ids, timeout, &cancel) {
+                        let _ = sender.send(host);
+                    }
+                    done.fetch_add(1, Ordering::Relaxed);
+                    ctx.request_repaint();
+                }
+            });
+        }
+
+        self.run = Some(Run {
+            hosts: receiver,
+            cancel,
+            done,
+            total,
+        });
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some(run) = self.run.take() {
+            run.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Collects what the probes found, the run is over once every prober has quit
+    fn collect(&mut self) {
+        if let Some(run) = &self.run {
+            loop {
+                match run.hosts.try_recv() {
+                    Ok(host) => self.results.push(host),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.run = None;
+                        break;
+                    }
+                }
+            }
+            self.results.sort_by_key(|x| (x.ip, x.port));
+        }
+    }
+
+    /// Draws the discovery window, returns devices the user chose to add
+    pub fn draw_window(&mut self, ctx: &egui::Context) -> Vec<crate::ModbusDevice> {
+        self.collect();
+        let mut add = vec![];
+        let mut open = self.open;
+
+        egui::Window::new("Discover Devices")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add_sized([100.0, 10.0], egui::Label::new("Addresses:"));
+                    ui.add_sized(
+                        [200.0, 10.0],
+                        egui::TextEdit::singleline(&mut self.hosts)
+                            .hint_text("10.0.0.0/24, 10.0.1.5-10.0.1.20"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.add_sized([100.0, 10.0], egui::Label::new("TCP Ports:"));
+                    ui.add_sized(
+                        [200.0, 10.0],
+                        egui::TextEdit::singleline(&mut self.ports).hint_text("502, 5020-5022"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.add_sized([100.0, 10.0], egui::Label::new("Unit IDs:"));
+                    ui.add_sized(
+                        [200.0, 10.0],
+                        egui::TextEdit::singleline(&mut self.unit_ids).hint_text("1, 255"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.add_sized([100.0, 10.0], egui::Label::new("Parallel:"));
+                    ui.add(
+                        egui::DragValue::new(&mut self.concurrency)
+                            .clamp_range(1..=256)
+                            .speed(0.0),
+                    )
+                    .on_hover_cursor(egui::CursorIcon::Text);
+                    ui.label("Timeout:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.timeout_ms)
+                            .clamp_range(10..=10000)
+                            .suffix(" ms")
+                            .speed(0.0),
+                    )
+                    .on_hover_cursor(egui::CursorIcon::Text);
+                });
+
+                ui.horizontal(|ui| {
+                    if self.running() {
+                        if ui.button("\u{23F9} Cancel").clicked() {
+                            self.cancel();
+                        }
+                    } else if ui.button("\u{23F5} Discover").clicked() {
+                        self.start(ctx);
+                    }
+                    ui.label(self.error.as_str());
+                });
+
+                if let Some(run) = &self.run {
+                    let done = run.done.load(Ordering::Relaxed);
+                    ui.add(
+                        egui::ProgressBar::new(done as f32 / run.total as f32)
+                            .text(format!("{} of {} targets", done, run.total)),
+                    );
+                }
+
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("Discovered Hosts")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label("Host");
+                                ui.label("Unit");
+                                ui.label("Latency");
+                                ui.label("");
+                                ui.end_row();
+                                for x in self.results.iter() {
+                                    ui.label(format!("{}:{}", x.ip, x.port));
+                                    ui.label(x.unit_id.to_string());
+                                    ui.label(format!("{} ms", x.latency.as_millis()))
+                                        .on_hover_text(match x.exception {
+                                            Some(code) => format!(
+                                                "Answered with exception {:02X}, {}",
+                                                code,
+                                                crate::transport::exception_text(code)
+                                            ),
+                                            None => "Answered the read".to_owned(),
+                                        });
+                                    if ui.button("Add").clicked() {
+                                        add.push(new_device(x));
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+
+                if !self.results.is_empty() && ui.button("Add All").clicked() {
+                    add.extend(self.results.iter().map(new_device));
+                }
+            });
+
+        self.open = open;
+        add
+    }
+}
+
+fn new_device(host: &DiscoveredHost) -> crate::ModbusDevice {
+    let mut device = crate::ModbusDevice::new();
+    device.lable = format!("{}:{}", host.ip, host.port);
+    device.ip = host.ip.to_string();
+    device.port = host.port.to_string();
+    device.unit_id = host.unit_id;
+    device
+}
+
+/// Any answer counts, an exception still means a Modbus server is listening.
+/// Only gateway exceptions are no answer, they tell that no unit behind it replied.
+fn probe(
+    ip: Ipv4Addr,
+    port: u16,
+    unit_ids: &[u8],
+    timeout: Duration,
+    cancel: &AtomicBool,
+) -> Option<DiscoveredHost> {
+    let mut settings = crate::transport::LinkSettings {
+        transport: crate::transport::Transport::Tcp,
+        ip: ip.to_string(),
+        port: port.to_string(),
+        serial: Default::default(),
+        tls: Default::default(),
+        timing: Default::default(),
+        gateway: false,
+    };
+    settings.timing.connect_timeout_ms = timeout.as_millis() as u64;
+    settings.timing.response_timeout_ms = timeout.as_millis() as u64;
+    let mut con = crate::transport::Connection::open(&settings).ok()?;
+
+    for unit_id in unit_ids {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut request = Vec::new();
+        ModbusRequest::new(*unit_id, ModbusProto::TcpUdp)
+            .generate_get_holdings(0, 1, &mut request)
+            .ok()?;
+
+        let start = Instant::now();
+        match con.exchange(&request) {
+            Ok(response) => {
+                match crate::transport::exception_code(&response, ModbusProto::TcpUdp) {
+                    // A gateway saying no unit behind it answered, same as a timeout
+                    Some(0x0A | 0x0B) => (),
+                    exception => {
+                        return Some(DiscoveredHost {
+                            ip,
+                            port,
+                            unit_id: *unit_id,
+                            latency: start.elapsed(),
+                            exception,
+                        })
+                    }
+                }
+            }
+            // Not this unit, maybe the next one
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => (),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// Reads "10.0.0.0/24", "10.0.0.5-10.0.0.9", "10.0.0.5-9" and single addresses
+fn parse_hosts(text: &str) -> Result<Vec<Ipv4Addr>, String> {
+    let mut hosts = vec![];
+    for part in text.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+        let parse = |ip: &str| {
+            ip.trim()
+                .parse::<Ipv4Addr>()
+                .map_err(|_| format!("\"{}\" is not an IPv4 address", ip.trim()))
+        };
+        let (first, last) = if let Some((ip, prefix)) = part.split_once('/') {
+            let prefix = match prefix.trim().parse::<u32>() {
+                Ok(prefix) if (16..=32).contains(&prefix) => prefix,
+                _ => return Err(format!("\"{}\" needs a prefix length from 16 to 32", part)),
+            };
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            let network = u32::from(parse(ip)?) & mask;
+            let broadcast = network | !mask;
+            // Network and broadcast addresses are no hosts, except in the tiniest blocks
+            if prefix < 31 {
+                (network + 1, broadcast - 1)
+            } else {
+                (network, broadcast)
+            }
+        } else if let Some((first, last)) = part.split_once('-') {
+            let first = u32::from(parse(first)?);
+            let last = match last.trim().parse::<u8>() {
+                Ok(octet) => (first & 0xFFFF_FF00) | octet as u32,
+                Err(_) => u32::from(parse(last)?),
+            };
+            (first, last)
+        } else {
+            let ip = u32::from(parse(part)?);
+            (ip, ip)
+        };
+        if last < first {
+            return Err(format!("\"{}\" ends before it starts", part));
+        }
+        if (last - first) as usize >= MAX_TARGETS {
+            return Err(format!("\"{}\" has too many addresses", part));
+        }
+        hosts.extend((first..=last).map(Ipv4Addr::from));
+    }
+    if hosts.is_empty() {
+        return Err("No addresses given".to_owned());
+    }
+    Ok(hosts)
+}
+
+/// Reads "502, 5020-5022" style lists
+fn parse_numbers(text: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let mut numbers = vec![];
+    for part in text.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        match (first.trim().parse::<u32>(), last.trim().parse::<u32>()) {
+            (Ok(first), Ok(last)) if min <= first && first <= last && last <= max => {
+                numbers.extend(first..=last)
+            }
+            _ => {
+                return Err(format!(
+                    "\"{}\" is not a number from {} to {}",
+                    part, min, max
+                ))
+            }
+        }
+    }
+    if numbers.is_empty() {
+        return Err("No numbers given".to_owned());
+    }
+    Ok(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> Ipv4Addr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_hosts_of_cidr_blocks_leaves_out_network_and_broadcast() {
+        let hosts = parse_hosts("10.0.0.7/24").unwrap();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], ip("10.0.0.1"));
+        assert_eq!(hosts[253], ip("10.0.0.254"));
+        assert_eq!(parse_hosts("10.0.0.0/16").unwrap().len(), 65534);
+    }
+
+    #[test]
+    fn parse_hosts_of_the_smallest_blocks() {
+        assert_eq!(parse_hosts("10.0.0.5/32").unwrap(), [ip("10.0.0.5")]);
+        assert_eq!(
+            parse_hosts("10.0.0.5/31").unwrap(),
+            [ip("10.0.0.4"), ip("10.0.0.5")]
+        );
+    }
+
+    #[test]
+    fn parse_hosts_refuses_prefixes_outside_16_to_32() {
+        assert!(parse_hosts("10.0.0.0/15").is_err());
+        assert!(parse_hosts("10.0.0.0/8").is_err());
+        assert!(parse_hosts("10.0.0.0/33").is_err());
+        assert!(parse_hosts("10.0.0.0/x").is_err());
+    }
+
+    #[test]
+    fn parse_hosts_of_ranges() {
+        assert_eq!(
+            parse_hosts("10.0.0.254-10.0.1.1").unwrap(),
+            [
+                ip("10.0.0.254"),
+                ip("10.0.0.255"),
+                ip("10.0.1.0"),
+                ip("10.0.1.1")
+            ]
+        );
+        assert_eq!(
+            parse_hosts("10.0.0.5-7").unwrap(),
+            [ip("10.0.0.5"), ip("10.0.0.6"), ip("10.0.0.7")]
+        );
+        assert!(parse_hosts("10.0.0.9-5").is_err());
+    }
+
+    #[test]
+    fn parse_hosts_of_lists() {
+        assert_eq!(
+            parse_hosts(" 10.0.0.1, ,10.0.0.3 ").unwrap(),
+            [ip("10.0.0.1"), ip("10.0.0.3")]
+        );
+    }
+
+    #[test]
+    fn parse_hosts_refuses_bad_input() {
+        assert!(parse_hosts("").is_err());
+        assert!(parse_hosts("10.0.0").is_err());
+        assert!(parse_hosts("10.0.0.1-host").is_err());
+        assert!(parse_hosts("10.0.0.0-10.1.0.0").is_err());
+    }
+}
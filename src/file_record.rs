@@ -0,0 +1,195 @@
+/// One sub-request of Read or Write File Record
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+pub struct FileRecord {
+    pub file: u16,
+    pub record: u16,
+    /// Number of registers
+    pub length: u16,
+}
+
+impl Default for FileRecord {
+    fn default() -> Self {
+        Self {
+            file: 1,
+            record: 0,
+            length: 1,
+        }
+    }
+}
+
+/// The only reference type the specification defines
+const REFERENCE_TYPE: u8 = 6;
+
+/// Registers of all records together, the order they are read or written in
+pub fn total_length(records: &[FileRecord]) -> usize {
+    records.iter().map(|x| x.length as usize).sum()
+}
+
+pub fn read_request_pdu(records: &[FileRecord]) -> Result<Vec<u8>, String> {
+    let response_len: usize = records.iter().map(|x| 2 + 2 * x.length as usize).sum();
+    if records.is_empty() || 7 * records.len() > 0xF5 || response_len > 0xF5 {
+        return Err("The records don't fit in one request and response".to_owned());
+    }
+    let mut pdu = vec![20, 7 * records.len() as u8];
+    for x in records {
+        pdu.push(REFERENCE_TYPE);
+        pdu.extend_from_slice(&x.file.to_be_bytes());
+        pdu.extend_from_slice(&x.record.to_be_bytes());
+        pdu.extend_from_slice(&x.length.to_be_bytes());
+    }
+    Ok(pdu)
+}
+
+/// Records are written from consecutive registers of `values`
+pub fn write_request_pdu(records: &[FileRecord], values: &[u16]) -> Result<Vec<u8>, String> {
+    let request_len: usize = records.iter().map(|x| 7 + 2 * x.length as usize).sum();
+    if records.is_empty() || request_len > 0xFB {
+        return Err("The records don't fit in one request".to_owned());
+    }
+    let mut pdu = vec![21, request_len as u8];
+    let mut values = values.iter();
+    for x in records {
+        pdu.push(REFERENCE_TYPE);
+        pdu.extend_from_slice(&x.file.to_be_bytes());
+        pdu.extend_from_slice(&x.record.to_be_bytes());
+        pdu.extend_from_slice(&x.length.to_be_bytes());
+        for _ in 0..x.length {
+            pdu.extend_from_slice(&values.next().copied().unwrap_or(0).to_be_bytes());
+        }
+    }
+    Ok(pdu)
+}
+
+/// Data of all records one after the other, `None` unless every record
+/// came back as long as asked for
+pub fn parse_read_response(pdu: &[u8], records: &[FileRecord]) -> Option<Vec<u8>> {
+    let [20, len, rest @ ..] = pdu else {
+        return None;
+    };
+    let mut rest = rest.get(..*len as usize)?;
+    let mut data = vec![];
+    for x in records {
+        let [sub_len, REFERENCE_TYPE, tail @ ..] = rest else {
+            return None;
+        };
+        let bytes = (*sub_len as usize).checked_sub(1)?;
+        if bytes != 2 * x.length as usize {
+            return None;
+        }
+        data.extend_from_slice(tail.get(..bytes)?);
+        rest = &tail[bytes..];
+    }
+    Some(data)
+}
+
+/// Editor of the sub-requests, says when the registers they take changed.
+/// Their data is numbered from `first_reg` on, like the data grid numbers it.
+pub fn draw_file_records(
+    ui: &mut egui::Ui,
+    records: &mut Vec<FileRecord>,
+    first_reg: usize,
+) -> bool {
+    let before = total_length(records);
+    let count = records.len();
+    egui::Grid::new("file_records")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("File");
+            ui.label("Record");
+            ui.label("Length");
+            ui.label("Data");
+            ui.end_row();
+
+            let mut start = first_reg;
+            records.retain_mut(|x| {
+                ui.add(
+                    egui::DragValue::new(&mut x.file)
+                        .clamp_range(1..=u16::MAX)
+                        .speed(0.0),
+                )
+                .on_hover_cursor(egui::CursorIcon::Text);
+                ui.add(
+                    egui::DragValue::new(&mut x.record)
+                        .clamp_range(0..=9999)
+                        .speed(0.0),
+                )
+                .on_hover_cursor(egui::CursorIcon::Text);
+                ui.add(
+                    egui::DragValue::new(&mut x.length)
+                        .clamp_range(1..=120)
+                        .speed(0.0),
+                )
+                .on_hover_cursor(egui::CursorIcon::Text);
+                ui.label(format!(
+                    "Reg {} to {}",
+                    start,
+                    start + x.length as usize - 1
+                ));
+                start += x.length as usize;
+                let retain = !ui.button("\u{1F5D1}").clicked();
+                ui.end_row();
+                retain
+            });
+        });
+    if ui.button("Add Record").clicked() {
+        records.push(FileRecord::default());
+    }
+    records.len() != count || total_length(records) != before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(file: u16, record: u16, length: u16) -> FileRecord {
+        FileRecord {
+            file,
+            record,
+            length,
+        }
+    }
+
+    #[test]
+    fn read_request_pdu_lists_the_records() {
+        assert_eq!(
+            read_request_pdu(&[record(4, 1, 2), record(3, 9, 2)]).unwrap(),
+            [20, 14, 6, 0, 4, 0, 1, 0, 2, 6, 0, 3, 0, 9, 0, 2]
+        );
+        assert!(read_request_pdu(&[]).is_err());
+        assert!(read_request_pdu(&[record(1, 0, 123)]).is_err());
+    }
+
+    #[test]
+    fn write_request_pdu_takes_values_in_order() {
+        assert_eq!(
+            write_request_pdu(&[record(4, 7, 3)], &[0x06AF, 0x04BE, 0x100D]).unwrap(),
+            [21, 13, 6, 0, 4, 0, 7, 0, 3, 0x06, 0xAF, 0x04, 0xBE, 0x10, 0x0D]
+        );
+        // Missing values are written as 0
+        assert_eq!(
+            write_request_pdu(&[record(1, 0, 1)], &[]).unwrap(),
+            [21, 9, 6, 0, 1, 0, 0, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn parse_read_response_joins_the_records() {
+        let records = [record(4, 1, 2), record(3, 9, 1)];
+        let pdu = [20, 10, 5, 6, 0x0D, 0xFE, 0x00, 0x20, 3, 6, 0x33, 0xCD];
+        assert_eq!(
+            parse_read_response(&pdu, &records).unwrap(),
+            [0x0D, 0xFE, 0x00, 0x20, 0x33, 0xCD]
+        );
+    }
+
+    #[test]
+    fn parse_read_response_rejects_short_records() {
+        let records = [record(4, 1, 2)];
+        // One register back where two were asked for
+        assert!(parse_read_response(&[20, 4, 3, 6, 0x0D, 0xFE], &records).is_none());
+        // Cut off before the end of the record
+        assert!(parse_read_response(&[20, 6, 5, 6, 0x0D, 0xFE, 0x00], &records).is_none());
+        assert!(parse_read_response(&[20, 6, 5, 7, 0x0D, 0xFE, 0x00, 0x20], &records).is_none());
+    }
+}
@@ -0,0 +1,335 @@
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IdentityAction {
+    Nothing,
+    Read,
+    Cancel,
+}
+
+/// Read Device ID code of FC43 / MEI 14, each stream includes the ones before it
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Clone, Copy)]
+pub enum IdAccess {
+    Basic = 1,
+    Regular = 2,
+    Extended = 3,
+    Individual = 4,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct IdObject {
+    pub id: u8,
+    pub value: Vec<u8>,
+}
+
+impl IdObject {
+    pub fn name(&self) -> String {
+        match self.id {
+            0x00 => "Vendor Name".to_owned(),
+            0x01 => "Product Code".to_owned(),
+            0x02 => "Revision".to_owned(),
+            0x03 => "Vendor URL".to_owned(),
+            0x04 => "Product Name".to_owned(),
+            0x05 => "Model Name".to_owned(),
+            0x06 => "Application Name".to_owned(),
+            id => format!("Object {:02X}", id),
+        }
+    }
+
+    /// Text as is, extended objects that aren't text as hex
+    pub fn value_text(&self) -> String {
+        match std::str::from_utf8(&self.value) {
+            Ok(text) if !text.chars().any(|c| c.is_control()) => text.to_owned(),
+            _ => self
+                .value
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+/// Vendor, product and revision as read with Read Device Identification.
+/// Saved with the device, so a device template tells which firmware it was made with.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
+pub struct DeviceIdentity {
+    pub access: IdAccess,
+    /// Object asked for by individual access
+    pub object_id: u8,
+    pub conformity: u8,
+    pub objects: Vec<IdObject>,
+    #[serde(skip)]
+    error: String,
+    #[serde(skip)]
+    pending: Option<u64>,
+    #[serde(skip)]
+    unit_id: u8,
+    /// Objects of the read in progress, they replace `objects` once all are in
+    #[serde(skip)]
+    reading: Vec<IdObject>,
+    #[serde(skip)]
+    reading_conformity: u8,
+}
+
+impl Default for DeviceIdentity {
+    fn default() -> Self {
+        Self {
+            access: IdAccess::Basic,
+            object_id: 0,
+            conformity: 0,
+            objects: vec![],
+            error: Default::default(),
+            pending: None,
+            unit_id: 0,
+            reading: vec![],
+            reading_conformity: 0,
+        }
+    }
+}
+
+impl DeviceIdentity {
+    pub fn running(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn read(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+        unit_id: u8,
+    ) {
+        self.cancel(worker);
+        self.reading.clear();
+        self.reading_conformity = 0;
+        self.error.clear();
+        self.unit_id = unit_id;
+        // Nobody answers a broadcast, the read could only time out
+        if unit_id == 0 && settings.unit_0_is_broadcast() {
+            self.error = "Unit 0 is broadcast on this link, read a slave's own unit ID".to_owned();
+            return;
+        }
+        let object_id = match self.access {
+            IdAccess::Individual => self.object_id,
+            _ => 0,
+        };
+        self.send(worker, settings, object_id);
+    }
+
+    pub fn cancel(&mut self, worker: &crate::worker::Worker) {
+        if let Some(id) = self.pending.take() {
+            worker.cancel(id);
+        }
+    }
+
+    pub fn waits_for(&self, reply: &crate::worker::Reply) -> bool {
+        self.pending == Some(reply.id)
+    }
+
+    fn send(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+        object_id: u8,
+    ) {
+        let request = crate::transport::frame_request(
+            self.unit_id,
+            &[43, 14, self.access as u8, object_id],
+            settings.transport.proto(),
+        );
+        self.pending = Some(worker.submit(settings.clone(), request));
+    }
+
+    pub fn receive(
+        &mut self,
+        reply: crate::worker::Reply,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+    ) {
+        if self.pending.take().is_none() {
+            return;
+        }
+        let proto = settings.transport.proto();
+        let response = match reply.response {
+            Ok(response) => response,
+            Err(e) => {
+                self.error = e.to_string();
+                return;
+            }
+        };
+        if let Some(code) = crate::transport::exception_code(&response, proto) {
+            self.error = format!(
+                "Exception {:02X}, {}",
+                code,
+                crate::transport::exception_text(code)
+            );
+            return;
+        }
+
+        match self.parse(crate::transport::response_pdu(&response, proto)) {
+            // The rest didn't fit in one response, ask again from where it stopped
+            Some(Some(next_id)) if !self.reading.iter().any(|x| x.id == next_id) => {
+                self.send(worker, settings, next_id)
+            }
+            Some(_) => self.finish(),
+            None => self.error = "Malformed identification response".to_owned(),
+        }
+    }
+
+    /// Takes the objects of one response, gives the next object to ask for
+    /// when more follow, `None` if the response doesn't add up
+    fn parse(&mut self, pdu: &[u8]) -> Option<Option<u8>> {
+        let [43, 14, _, conformity, more_follows, next_id, count, objects @ ..] = pdu else {
+            return None;
+        };
+        self.reading_conformity = *conformity;
+
+        let mut rest = objects;
+        for _ in 0..*count {
+            let [id, len, tail @ ..] = rest else {
+                return None;
+            };
+            let value = tail.get(..*len as usize)?;
+            self.reading.retain(|x| x.id != *id);
+            self.reading.push(IdObject {
+                id: *id,
+                value: value.to_vec(),
+            });
+            rest = &tail[*len as usize..];
+        }
+
+        Some((*more_follows == 0xFF && self.access != IdAccess::Individual).then_some(*next_id))
+    }
+
+    /// All objects are in, they replace those of the last read
+    fn finish(&mut self) {
+        self.objects = std::mem::take(&mut self.reading);
+        self.conformity = self.reading_conformity;
+    }
+
+    /// Vendor, product and revision in one line, for the hover of the device
+    pub fn summary(&self) -> String {
+        [0x00, 0x01, 0x02]
+            .iter()
+            .filter_map(|id| self.objects.iter().find(|x| x.id == *id))
+            .map(|x| x.value_text())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn draw_identity(&mut self, ui: &mut egui::Ui) -> IdentityAction {
+        let mut action = IdentityAction::Nothing;
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Access:"));
+            egui::ComboBox::from_id_source("Identification Access")
+                .selected_text(format!("{:?}", self.access))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.access, IdAccess::Basic, "Basic");
+                    ui.selectable_value(&mut self.access, IdAccess::Regular, "Regular");
+                    ui.selectable_value(&mut self.access, IdAccess::Extended, "Extended");
+                    ui.selectable_value(&mut self.access, IdAccess::Individual, "Individual");
+                });
+            if self.access == IdAccess::Individual {
+                ui.label("Object:");
+                ui.add(
+                    egui::DragValue::new(&mut self.object_id)
+                        .clamp_range(0..=u8::MAX)
+                        .speed(0.0)
+                        .hexadecimal(2, false, true),
+                )
+                .on_hover_cursor(egui::CursorIcon::Text);
+            }
+
+            if self.running() {
+                if ui.button("\u{23F9} Cancel").clicked() {
+                    action = IdentityAction::Cancel;
+                }
+                ui.spinner();
+            } else if ui.button("\u{23F5} Read").clicked() {
+                action = IdentityAction::Read;
+            }
+        });
+
+        if !self.error.is_empty() {
+            ui.label(self.error.as_str());
+        }
+        for x in self.objects.iter() {
+            ui.horizontal(|ui| {
+                ui.add_sized([100.0, 10.0], egui::Label::new(format!("{}:", x.name())));
+                ui.label(x.value_text());
+            });
+        }
+        if !self.objects.is_empty() {
+            ui.label(format!("Conformity level {:02X}", self.conformity));
+        }
+
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Response PDU with objects given as id and text
+    fn response(more_follows: u8, next_id: u8, objects: &[(u8, &str)]) -> Vec<u8> {
+        let mut pdu = vec![43, 14, 1, 0x81, more_follows, next_id, objects.len() as u8];
+        for (id, text) in objects {
+            pdu.push(*id);
+            pdu.push(text.len() as u8);
+            pdu.extend_from_slice(text.as_bytes());
+        }
+        pdu
+    }
+
+    #[test]
+    fn parse_takes_all_objects() {
+        let mut x = DeviceIdentity::default();
+        let pdu = response(0, 0, &[(0, "Acme"), (1, "PX-1"), (2, "1.2")]);
+        assert_eq!(x.parse(&pdu), Some(None));
+        x.finish();
+        assert_eq!(x.conformity, 0x81);
+        assert_eq!(x.summary(), "Acme PX-1 1.2");
+    }
+
+    #[test]
+    fn parse_asks_for_more_when_more_follow() {
+        let mut x = DeviceIdentity::default();
+        assert_eq!(
+            x.parse(&response(0xFF, 2, &[(0, "Acme"), (1, "PX-1")])),
+            Some(Some(2))
+        );
+        assert_eq!(x.parse(&response(0, 0, &[(2, "1.2")])), Some(None));
+        x.finish();
+        assert_eq!(x.summary(), "Acme PX-1 1.2");
+    }
+
+    #[test]
+    fn objects_stay_until_a_read_finishes() {
+        let mut x = DeviceIdentity::default();
+        x.parse(&response(0, 0, &[(0, "Acme"), (1, "PX-1"), (2, "1.2")]));
+        x.finish();
+        assert_eq!(x.parse(&response(0xFF, 1, &[(0, "Acme")])), Some(Some(1)));
+        assert_eq!(x.summary(), "Acme PX-1 1.2");
+        assert_eq!(x.parse(&[43, 14, 1]), None);
+        assert_eq!(x.summary(), "Acme PX-1 1.2");
+    }
+
+    #[test]
+    fn parse_of_individual_access_never_asks_for_more() {
+        let mut x = DeviceIdentity {
+            access: IdAccess::Individual,
+            ..Default::default()
+        };
+        assert_eq!(x.parse(&response(0xFF, 5, &[(4, "Pump")])), Some(None));
+    }
+
+    #[test]
+    fn parse_rejects_cut_off_objects() {
+        let mut x = DeviceIdentity::default();
+        let mut pdu = response(0, 0, &[(0, "Acme")]);
+        pdu.pop();
+        assert_eq!(x.parse(&pdu), None);
+        assert_eq!(x.parse(&[43, 14, 1]), None);
+    }
+}
@@ -3,10 +3,32 @@
 mod app;
 pub use app::ModbusApp;
 
+mod capability;
+
 mod device;
 pub use device::ModbusDevice;
 
+mod diagnostics;
+
+mod discovery;
+
+mod file_record;
+
+mod identity;
+
 mod query;
 pub use query::QueryWrapper;
 
+mod regmap;
+
+mod scan;
+
+mod text;
+
+mod tls;
+
+mod transport;
+
 mod watched;
+
+mod worker;
@@ -1,11 +1,6 @@
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::time::Duration;
+use rmodbus::client::ModbusRequest;
 
-use byteorder::{ByteOrder, LittleEndian};
-use rmodbus::{client::ModbusRequest, guess_response_frame_len, ModbusProto};
-
-#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Clone, Copy)]
 #[repr(u8)]
 pub enum FC {
     ReadCoils = 1,
@@ -14,8 +9,60 @@ pub enum FC {
     ReadInputRegisters = 4,
     WriteCoil = 5,
     WriteHoldingRegister = 6,
+    ReadExceptionStatus = 7,
+    Diagnostics = 8,
+    GetCommEventCounter = 11,
+    GetCommEventLog = 12,
     WriteCoils = 15,
     WriteHoldingRegisters = 16,
+    ReportServerId = 17,
+    ReadFileRecord = 20,
+    WriteFileRecord = 21,
+    MaskWriteRegister = 22,
+    ReadWriteRegisters = 23,
+    ReadFifoQueue = 24,
+    /// Any function code, sent as `raw_function_code` with `raw_payload`
+    Custom = 0,
+}
+
+impl FC {
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            FC::WriteCoil
+                | FC::WriteHoldingRegister
+                | FC::WriteCoils
+                | FC::WriteHoldingRegisters
+                | FC::MaskWriteRegister
+                | FC::WriteFileRecord
+        )
+    }
+
+    /// Serial line function codes, answered with fields instead of data
+    pub fn is_diagnostic(&self) -> bool {
+        matches!(
+            self,
+            FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId
+        )
+    }
+
+    /// Most registers, or bits, one request can take. Responses are read up to 256
+    /// bytes, where the MBAP header takes 3 bytes more than RTU and ASCII framing.
+    pub fn max_count(&self, proto: rmodbus::ModbusProto) -> u16 {
+        let mbap = proto == rmodbus::ModbusProto::TcpUdp;
+        match self {
+            FC::ReadCoils | FC::ReadDiscreteInput if mbap => 1976,
+            FC::ReadCoils | FC::ReadDiscreteInput => 2000,
+            FC::ReadHoldingRegisters | FC::ReadInputRegisters if mbap => 123,
+            FC::ReadHoldingRegisters | FC::ReadInputRegisters => 125,
+            FC::WriteCoils => 1968,
+            _ => 122,
+        }
+    }
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Clone, Copy)]
@@ -25,7 +72,53 @@ pub enum DataView {
     Unsigned32bit,
     Signed32bit,
     Float32bit,
+    Unsigned64bit,
+    Signed64bit,
+    Float64bit,
     Hexadecimal,
+    Text,
+    /// The 16 bits of each register
+    Bits,
+}
+
+/// Order the bytes of a value are sent in over its registers, A being the
+/// most significant byte. Values of one register only swap their bytes.
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Clone, Copy)]
+pub enum Endianness {
+    Abcd,
+    Cdab,
+    Badc,
+    Dcba,
+}
+
+impl Endianness {
+    pub const ALL: [Endianness; 4] = [
+        Endianness::Abcd,
+        Endianness::Cdab,
+        Endianness::Badc,
+        Endianness::Dcba,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Endianness::Abcd => "ABCD",
+            Endianness::Cdab => "CDAB",
+            Endianness::Badc => "BADC",
+            Endianness::Dcba => "DCBA",
+        }
+    }
+
+    /// Turns register bytes into the value most significant byte first, and back
+    pub fn swap<const N: usize>(self, mut bytes: [u8; N]) -> [u8; N] {
+        if matches!(self, Endianness::Cdab | Endianness::Dcba) {
+            bytes.reverse();
+            bytes.chunks_exact_mut(2).for_each(|x| x.swap(0, 1));
+        }
+        if matches!(self, Endianness::Badc | Endianness::Dcba) {
+            bytes.chunks_exact_mut(2).for_each(|x| x.swap(0, 1));
+        }
+        bytes
+    }
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -34,17 +127,60 @@ pub struct QueryWrapper {
     pub lable: String,
     pub reg: u16,
     pub count: u16,
-    pub tr_id: u8,
+    /// Slave addressed instead of the device unit ID, for devices that are gateways
+    pub override_unit_id: bool,
     pub unit_id: u8,
     pub function_code: FC,
     pub read_buffer: Vec<u8>,
     pub write_buffer: Vec<u8>,
+    /// Coils and discrete inputs, the buffers above only keep registers
+    pub read_coils: Vec<bool>,
+    pub write_coils: Vec<bool>,
     pub selected: bool,
     pub response: String,
     pub data_veiw1: DataView,
+    pub endianness: Endianness,
+    /// Registers shown by the text view
+    pub text: crate::text::TextView,
+    /// Names of coils, or of register bits counted from bit 0 of the first register
+    pub bit_names: Vec<String>,
     pub factor: f32,
     pub value_offsett: f32,
     pub watched_list: Vec<crate::watched::WatchedReg>,
+    /// Run on its own while polling is started
+    pub poll_enabled: bool,
+    pub poll_interval_ms: u64,
+    /// Mask Write Register leaves the bits set in the AND mask as they are
+    /// and sets the others to the OR mask
+    pub and_mask: u16,
+    pub or_mask: u16,
+    /// Registers written by Read/Write Multiple Registers, `reg` and `count` are read
+    pub write_reg: u16,
+    pub write_count: u16,
+    pub sub_function: crate::diagnostics::SubFunction,
+    pub diagnostic_data: u16,
+    /// Sub-requests of Read and Write File Record
+    pub file_records: Vec<crate::file_record::FileRecord>,
+    pub raw_function_code: u8,
+    /// Hex bytes following the function code
+    pub raw_payload: String,
+    /// Shows the response data through the data view, from this byte on
+    pub raw_decode: bool,
+    pub raw_data_offset: u8,
+    /// Text being typed into the text view, the write buffer only keeps it padded
+    #[serde(skip)]
+    text_edit: String,
+    #[serde(skip)]
+    next_poll: Option<std::time::Instant>,
+    #[serde(skip)]
+    pub raw_request: String,
+    #[serde(skip)]
+    pub raw_response: String,
+    /// Response PDU of diagnostic and custom function codes
+    #[serde(skip)]
+    response_pdu: Vec<u8>,
+    #[serde(skip)]
+    pending: Option<(u64, ModbusRequest)>,
 }
 
 impl Default for QueryWrapper {
@@ -54,17 +190,41 @@ impl Default for QueryWrapper {
             lable: "New Query".to_owned(),
             reg: 0,
             count: 0,
-            tr_id: 0,
+            override_unit_id: false,
             unit_id: 0,
             function_code: FC::ReadCoils,
             read_buffer: vec![0, 247],
             write_buffer: vec![0, 247],
+            read_coils: vec![],
+            write_coils: vec![false],
             selected: false,
             response: "Not Executed".to_owned(),
             data_veiw1: DataView::Unsigned16bit,
+            endianness: Endianness::Cdab,
+            text: Default::default(),
+            bit_names: vec![],
+            text_edit: Default::default(),
             factor: 1.,
             value_offsett: 0.,
             watched_list: vec![],
+            poll_enabled: false,
+            poll_interval_ms: 1000,
+            and_mask: 0xFFFF,
+            or_mask: 0,
+            write_reg: 0,
+            write_count: 1,
+            sub_function: crate::diagnostics::SubFunction::ReturnQueryData,
+            diagnostic_data: 0xA537,
+            file_records: vec![Default::default()],
+            raw_function_code: 65,
+            raw_payload: Default::default(),
+            raw_decode: false,
+            raw_data_offset: 0,
+            next_poll: None,
+            raw_request: Default::default(),
+            raw_response: Default::default(),
+            response_pdu: vec![],
+            pending: None,
         }
     }
 }
@@ -76,78 +236,211 @@ impl QueryWrapper {
             lable: "New Query".to_owned(),
             reg: 0,
             count: 1,
-            tr_id: 1,
+            override_unit_id: false,
             unit_id: 1,
             function_code: FC::ReadCoils,
             read_buffer: vec![0, 247],
             write_buffer: vec![0, 247],
+            read_coils: vec![],
+            write_coils: vec![false],
             selected: false,
             response: "Not Executed".to_owned(),
             data_veiw1: DataView::Unsigned16bit,
+            endianness: Endianness::Cdab,
+            text: Default::default(),
+            bit_names: vec![],
+            text_edit: Default::default(),
             factor: 1.,
             value_offsett: 0.,
             watched_list: vec![],
+            poll_enabled: false,
+            poll_interval_ms: 1000,
+            and_mask: 0xFFFF,
+            or_mask: 0,
+            write_reg: 0,
+            write_count: 1,
+            sub_function: crate::diagnostics::SubFunction::ReturnQueryData,
+            diagnostic_data: 0xA537,
+            file_records: vec![Default::default()],
+            raw_function_code: 65,
+            raw_payload: Default::default(),
+            raw_decode: false,
+            raw_data_offset: 0,
+            next_poll: None,
+            raw_request: Default::default(),
+            raw_response: Default::default(),
+            response_pdu: vec![],
+            pending: None,
+        }
+    }
+
+    /// Hands the request to the device worker, the reply is picked up by `receive`
+    pub fn execute(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+        device_unit_id: u8,
+    ) {
+        let unit_id = self.addressed_unit(device_unit_id);
+        let broadcast = unit_id == 0 && settings.unit_0_is_broadcast();
+        if let Some((mreq, request)) =
+            self.build_request(unit_id, settings.transport.proto(), broadcast)
+        {
+            let id = worker.submit(settings.clone(), request);
+            self.pending = Some((id, mreq));
+            self.response = "Waiting for response".to_owned();
+        }
+    }
+
+    /// Executes the query if polling is due, returns the time until the next poll
+    pub fn poll(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+        device_unit_id: u8,
+        now: std::time::Instant,
+    ) -> Option<std::time::Duration> {
+        if !self.poll_enabled {
+            return None;
+        }
+        // A slow device is never asked again before it has answered
+        if !self.in_flight() && self.next_poll.map_or(true, |next| next <= now) {
+            self.next_poll = Some(now + std::time::Duration::from_millis(self.poll_interval_ms));
+            self.execute(worker, settings, device_unit_id);
+        }
+        // The worker repaints when the reply comes, waiting for it needs no wakeup
+        if self.in_flight() {
+            return None;
         }
+        self.next_poll
+            .map(|next| next.saturating_duration_since(now))
     }
 
-    pub fn execute(&mut self, ip: &String, port: &String) {
-        let mut mreq = ModbusRequest::new(self.tr_id, ModbusProto::TcpUdp);
+    /// Unit the request goes to, the device unit ID unless overridden
+    pub fn addressed_unit(&self, device_unit_id: u8) -> u8 {
+        if self.override_unit_id {
+            self.unit_id
+        } else {
+            device_unit_id
+        }
+    }
+
+    pub fn in_flight(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn cancel(&mut self, worker: &crate::worker::Worker) {
+        if let Some((id, _)) = self.pending.take() {
+            worker.cancel(id);
+            self.response = "Cancelled".to_owned();
+        }
+    }
+
+    pub fn waits_for(&self, reply: &crate::worker::Reply) -> bool {
+        matches!(self.pending, Some((id, _)) if id == reply.id)
+    }
+
+    pub fn receive(&mut self, reply: crate::worker::Reply) {
+        if let Some((_, mut mreq)) = self.pending.take() {
+            mreq.tr_id = reply.transaction_id;
+            self.raw_request = reply.raw_request;
+            self.raw_response = reply.raw_response;
+            match reply.response {
+                // Only a broadcast is done without an answer
+                Ok(response) if response.is_empty() => {
+                    self.response = "Broadcast sent, slaves do not answer".to_owned()
+                }
+                Ok(response) => self.parse_response(mreq, &response),
+                Err(e) => self.response = e.to_string(),
+            }
+        }
+    }
+
+    fn build_request(
+        &mut self,
+        unit_id: u8,
+        proto: rmodbus::ModbusProto,
+        broadcast: bool,
+    ) -> Option<(ModbusRequest, Vec<u8>)> {
+        if broadcast && !self.function_code.is_write() {
+            self.response = "Unit 0 is broadcast, only writes can be sent to it".to_owned();
+            return None;
+        }
+        let mut mreq = ModbusRequest::new(unit_id, proto);
         let mut request = Vec::new();
         match &mut self.function_code {
-            FC::ReadCoils => match mreq.generate_get_coils(self.reg, self.count * 16, &mut request)
-            {
+            FC::ReadCoils => match mreq.generate_get_coils(self.reg, self.count, &mut request) {
                 Ok(_) => (),
-                Err(e) => self.response = e.to_string(),
+                Err(e) => {
+                    self.response = e.to_string();
+                    return None;
+                }
             },
             FC::ReadDiscreteInput => {
-                match mreq.generate_get_discretes(self.reg, self.count * 16, &mut request) {
+                match mreq.generate_get_discretes(self.reg, self.count, &mut request) {
                     Ok(_) => (),
-                    Err(e) => self.response = e.to_string(),
+                    Err(e) => {
+                        self.response = e.to_string();
+                        return None;
+                    }
                 }
             }
             FC::ReadHoldingRegisters => {
                 match mreq.generate_get_holdings(self.reg, self.count, &mut request) {
                     Ok(_) => (),
-                    Err(e) => self.response = e.to_string(),
+                    Err(e) => {
+                        self.response = e.to_string();
+                        return None;
+                    }
                 }
             }
             FC::ReadInputRegisters => {
                 match mreq.generate_get_inputs(self.reg, self.count, &mut request) {
                     Ok(_) => (),
-                    Err(e) => self.response = e.to_string(),
+                    Err(e) => {
+                        self.response = e.to_string();
+                        return None;
+                    }
                 }
             }
             FC::WriteCoil => {
-                match mreq.generate_set_coil(self.reg, self.write_buffer[0] != 0, &mut request) {
+                match mreq.generate_set_coil(
+                    self.reg,
+                    self.write_coils.first() == Some(&true),
+                    &mut request,
+                ) {
                     Ok(_) => (),
-                    Err(e) => self.response = e.to_string(),
+                    Err(e) => {
+                        self.response = e.to_string();
+                        return None;
+                    }
                 }
             }
             FC::WriteHoldingRegister => {
                 match mreq.generate_set_holding(
                     self.reg,
-                    LittleEndian::read_u16(&self.write_buffer),
+                    self.write_buffer
+                        .get(..2)
+                        .map_or(0, |x| u16::from_ne_bytes([x[0], x[1]])),
                     &mut request,
                 ) {
                     Ok(_) => (),
                     Err(e) => {
                         self.response = e.to_string();
-                        return;
+                        return None;
+                    }
+                }
+            }
+            FC::WriteCoils => {
+                self.write_coils.resize(self.count as usize, false);
+                match mreq.generate_set_coils_bulk(self.reg, &self.write_coils, &mut request) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        self.response = e.to_string();
+                        return None;
                     }
                 }
             }
-            FC::WriteCoils => match mreq.generate_set_coils_bulk(
-                self.reg,
-                self.write_buffer
-                    .chunks_exact(2)
-                    .map(|a| u16::from_ne_bytes([a[0], a[1]]) != 0)
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-                &mut request,
-            ) {
-                Ok(_) => (),
-                Err(e) => self.response = e.to_string(),
-            },
             FC::WriteHoldingRegisters => {
                 match mreq.generate_set_holdings_bulk(
                     self.reg,
@@ -161,84 +454,188 @@ impl QueryWrapper {
                     Ok(_) => (),
                     Err(e) => {
                         self.response = e.to_string();
-                        return;
+                        return None;
                     }
                 }
             }
-        }
-
-        match QueryWrapper::connect(ip, port) {
-            Err(e) => self.response = e.to_string(),
-            Ok(mut con) => {
-                match con.write(&request) {
-                    Ok(_) => (),
-                    Err(e) => self.response = e.to_string(),
+            // rmodbus can't build these, but checks the answers once it knows the code
+            FC::MaskWriteRegister => {
+                mreq.func = 22;
+                let mut pdu = vec![22];
+                pdu.extend_from_slice(&self.reg.to_be_bytes());
+                pdu.extend_from_slice(&self.and_mask.to_be_bytes());
+                pdu.extend_from_slice(&self.or_mask.to_be_bytes());
+                request = crate::transport::frame_request(unit_id, &pdu, proto);
+            }
+            FC::ReadWriteRegisters => {
+                mreq.func = 23;
+                self.write_buffer.resize(self.write_count as usize * 2, 0);
+                let mut pdu = vec![23];
+                pdu.extend_from_slice(&self.reg.to_be_bytes());
+                pdu.extend_from_slice(&self.count.to_be_bytes());
+                pdu.extend_from_slice(&self.write_reg.to_be_bytes());
+                pdu.extend_from_slice(&self.write_count.to_be_bytes());
+                pdu.push(self.write_buffer.len() as u8);
+                for a in self.write_buffer.chunks_exact(2) {
+                    pdu.extend_from_slice(&u16::from_ne_bytes([a[0], a[1]]).to_be_bytes());
                 }
-
-                // read first 6 bytes of response frame
-                let mut buf = [0u8; 6];
-                match con.read_exact(&mut buf) {
-                    Ok(_) => (),
-                    Err(e) => self.response = e.to_string(),
+                request = crate::transport::frame_request(unit_id, &pdu, proto);
+            }
+            FC::ReadExceptionStatus
+            | FC::Diagnostics
+            | FC::GetCommEventCounter
+            | FC::GetCommEventLog
+            | FC::ReportServerId => {
+                mreq.func = self.function_code as u8;
+                let pdu = crate::diagnostics::request_pdu(
+                    self.function_code,
+                    self.sub_function,
+                    self.diagnostic_data,
+                );
+                request = crate::transport::frame_request(unit_id, &pdu, proto);
+            }
+            FC::ReadFileRecord | FC::WriteFileRecord => {
+                mreq.func = self.function_code as u8;
+                let pdu = match self.function_code {
+                    FC::ReadFileRecord => crate::file_record::read_request_pdu(&self.file_records),
+                    _ => {
+                        let length = crate::file_record::total_length(&self.file_records);
+                        self.write_buffer.resize(length * 2, 0);
+                        crate::file_record::write_request_pdu(
+                            &self.file_records,
+                            self.write_buffer
+                                .chunks_exact(2)
+                                .map(|a| u16::from_ne_bytes([a[0], a[1]]))
+                                .collect::<Vec<_>>()
+                                .as_slice(),
+                        )
+                    }
+                };
+                match pdu {
+                    Ok(pdu) => request = crate::transport::frame_request(unit_id, &pdu, proto),
+                    Err(e) => {
+                        self.response = e;
+                        return None;
+                    }
                 }
+            }
+            FC::Custom => {
+                let Some(payload) = parse_hex(&self.raw_payload) else {
+                    self.response = "Payload is not hex bytes".to_owned();
+                    return None;
+                };
+                mreq.func = self.raw_function_code;
+                let mut pdu = vec![self.raw_function_code];
+                pdu.extend(payload);
+                request = crate::transport::frame_request(unit_id, &pdu, proto);
+            }
+            FC::ReadFifoQueue => {
+                mreq.func = 24;
+                let mut pdu = vec![24];
+                pdu.extend_from_slice(&self.reg.to_be_bytes());
+                request = crate::transport::frame_request(unit_id, &pdu, proto);
+            }
+        }
+
+        Some((mreq, request))
+    }
 
-                let mut response = Vec::new();
-                response.extend_from_slice(&buf);
-                match guess_response_frame_len(&buf, ModbusProto::TcpUdp) {
-                    Ok(len) => {
-                        if len > 6 {
-                            let mut rest = vec![0u8; (len - 6) as usize];
-                            con.read_exact(&mut rest).unwrap();
-                            response.extend(rest);
+    fn parse_response(&mut self, mreq: ModbusRequest, response: &[u8]) {
+        // check if frame has no Modbus error inside
+        match mreq.parse_ok(response) {
+            Err(e) => self.response = e.to_string(),
+            Ok(_ok) => match self.function_code {
+                FC::ReadCoils | FC::ReadDiscreteInput => {
+                    let mut bits: Vec<bool> = vec![];
+                    match mreq.parse_bool(response, &mut bits) {
+                        Ok(_) => {
+                            self.read_coils = bits;
+                            self.response = "Read successful".to_owned()
                         }
+                        Err(e) => self.response = e.to_string(),
                     }
-                    Err(e) => self.response = e.to_string(),
-                }
-
-                // check if frame has no Modbus error inside
-                match mreq.parse_ok(&response) {
-                    Err(e) => self.response = e.to_string(),
-                    Ok(_ok) => match self.function_code {
-                        FC::ReadCoils | FC::ReadDiscreteInput => {
-                            let mut temphold: Vec<bool> = vec![];
-                            match mreq.parse_bool(&response, &mut temphold) {
-                                Ok(_) => {
-                                    self.read_buffer = unsafe { std::mem::transmute(temphold) }
-                                }
-                                Err(e) => self.response = e.to_string(),
-                            }
-                        }
-                        FC::ReadHoldingRegisters | FC::ReadInputRegisters => {
-                            response.drain(0..9);
-                            self.read_buffer = response;
+                }
+                FC::ReadHoldingRegisters | FC::ReadInputRegisters => {
+                    match mreq.parse_slice(response) {
+                        Ok(data) => {
+                            self.read_buffer = data.to_vec();
                             self.response = "Read successful".to_owned()
                         }
-                        FC::WriteCoil
-                        | FC::WriteCoils
-                        | FC::WriteHoldingRegister
-                        | FC::WriteHoldingRegisters => {
-                            self.response = "Write successful".to_owned()
+                        Err(e) => self.response = e.to_string(),
+                    }
+                }
+                FC::WriteCoil
+                | FC::WriteCoils
+                | FC::WriteHoldingRegister
+                | FC::WriteHoldingRegisters
+                | FC::MaskWriteRegister
+                | FC::WriteFileRecord => self.response = "Write successful".to_owned(),
+                FC::ReadWriteRegisters => {
+                    match crate::transport::response_pdu(response, mreq.proto) {
+                        [23, len, data @ ..] if data.len() == *len as usize => {
+                            self.read_buffer = data.to_vec();
+                            self.response = "Write and read successful".to_owned()
                         }
-                    },
+                        _ => self.response = rmodbus::ErrorKind::FrameBroken.to_string(),
+                    }
                 }
-            }
-        }
-    }
-
-    pub fn connect(ip: &String, port: &String) -> Result<TcpStream, std::io::Error> {
-        let timeout = Duration::from_secs(1);
-
-        match TcpStream::connect(format!("{}:{}", ip, port)) {
-            Ok(tcp_stream) => {
-                tcp_stream.set_read_timeout(Some(timeout))?;
-                tcp_stream.set_write_timeout(Some(timeout))?;
-                Ok(tcp_stream)
-            }
-            Err(e) => Err(e),
+                FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => {
+                    self.response_pdu =
+                        crate::transport::response_pdu(response, mreq.proto).to_vec();
+                    self.response = "Read successful".to_owned()
+                }
+                FC::ReadFileRecord => match crate::file_record::parse_read_response(
+                    crate::transport::response_pdu(response, mreq.proto),
+                    &self.file_records,
+                ) {
+                    Some(data) => {
+                        self.read_buffer = data;
+                        self.response = "Read successful".to_owned()
+                    }
+                    None => self.response = rmodbus::ErrorKind::FrameBroken.to_string(),
+                },
+                FC::Custom => {
+                    self.response_pdu =
+                        crate::transport::response_pdu(response, mreq.proto).to_vec();
+                    let data = self.response_pdu.get(1..).unwrap_or_default();
+                    let start = (self.raw_data_offset as usize).min(data.len());
+                    let mut data = data[start..].to_vec();
+                    data.truncate(data.len() / 2 * 2);
+                    self.read_buffer = data;
+                    self.response = match self.raw_decode && self.read_buffer.is_empty() {
+                        true => format!(
+                            "Response received, no register from byte {} on",
+                            self.raw_data_offset
+                        ),
+                        false => "Response received".to_owned(),
+                    }
+                }
+                FC::ReadFifoQueue => match crate::transport::response_pdu(response, mreq.proto) {
+                    [24, _, _, count_hi, count_lo, data @ ..]
+                        if data.len()
+                            == 2 * u16::from_be_bytes([*count_hi, *count_lo]) as usize =>
+                    {
+                        self.read_buffer = data.to_vec();
+                        self.response = format!("Read successful, {} in queue", data.len() / 2)
+                    }
+                    _ => self.response = rmodbus::ErrorKind::FrameBroken.to_string(),
+                },
+            },
         }
     }
 
-    pub fn draw_query_frame(&mut self, ui: &mut egui::Ui) {
+    pub fn draw_query_frame(
+        &mut self,
+        ui: &mut egui::Ui,
+        device_unit_id: u8,
+        slaves: &[crate::device::Slave],
+        capabilities: &crate::capability::CapabilityProbe,
+        proto: rmodbus::ModbusProto,
+    ) {
         ui.horizontal(|ui| {
             ui.add_sized([80.0, 10.0], egui::Label::new("Query Lable:"));
             ui.add_sized(
@@ -272,6 +669,26 @@ impl QueryWrapper {
                         FC::WriteHoldingRegister,
                         "FC6 Write Holding Register",
                     );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadExceptionStatus,
+                        "FC7 Read Exception Status",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::Diagnostics,
+                        "FC8 Diagnostics",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::GetCommEventCounter,
+                        "FC11 Get Comm Event Counter",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::GetCommEventLog,
+                        "FC12 Get Comm Event Log",
+                    );
                     ui.selectable_value(
                         &mut self.function_code,
                         FC::WriteCoils,
@@ -282,7 +699,50 @@ impl QueryWrapper {
                         FC::WriteHoldingRegisters,
                         "FC16 Write Holding Registers",
                     );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReportServerId,
+                        "FC17 Report Server ID",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadFileRecord,
+                        "FC20 Read File Record",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::WriteFileRecord,
+                        "FC21 Write File Record",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::MaskWriteRegister,
+                        "FC22 Mask Write Register",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadWriteRegisters,
+                        "FC23 Read/Write Multiple Registers",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadFifoQueue,
+                        "FC24 Read FIFO Queue",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::Custom,
+                        "Custom Function Code",
+                    );
                 });
+            let code = match self.function_code {
+                FC::Custom => self.raw_function_code,
+                function_code => function_code as u8,
+            };
+            if capabilities.rejects(code) {
+                ui.colored_label(ui.visuals().warn_fg_color, "\u{26A0}")
+                    .on_hover_text("The device answered this function code with Illegal Function");
+            }
         });
         ui.horizontal(|ui| {
             ui.add_sized([80.0, 10.0], egui::Label::new("Offset:"));
@@ -296,20 +756,227 @@ impl QueryWrapper {
             if ui
                 .add(
                     egui::DragValue::new(&mut self.count)
-                        .clamp_range(1..=122)
+                        .clamp_range(1..=self.function_code.max_count(proto))
                         .speed(0.0),
                 )
                 .on_hover_cursor(egui::CursorIcon::Text)
                 .lost_focus()
+                && !matches!(
+                    self.function_code,
+                    FC::ReadWriteRegisters | FC::WriteFileRecord
+                )
             {
-                self.write_buffer = vec![0; (self.count * 2) as usize]
+                match self.function_code {
+                    FC::WriteCoil | FC::WriteCoils => {
+                        self.write_coils = vec![false; self.count as usize]
+                    }
+                    _ => self.write_buffer = vec![0; (self.count * 2) as usize],
+                }
             }
             ui.label(self.response.as_str());
         });
+        match self.function_code {
+            FC::MaskWriteRegister => {
+                ui.horizontal(|ui| {
+                    ui.add_sized([80.0, 10.0], egui::Label::new("AND Mask:"));
+                    ui.add(
+                        egui::DragValue::new(&mut self.and_mask)
+                            .clamp_range(u16::MIN..=u16::MAX)
+                            .hexadecimal(4, false, true)
+                            .speed(0.0),
+                    )
+                    .on_hover_cursor(egui::CursorIcon::Text)
+                    .on_hover_text("Bits set here are kept as they are");
+                    ui.add_sized([80.0, 10.0], egui::Label::new("OR Mask:"));
+                    ui.add(
+                        egui::DragValue::new(&mut self.or_mask)
+                            .clamp_range(u16::MIN..=u16::MAX)
+                            .hexadecimal(4, false, true)
+                            .speed(0.0),
+                    )
+                    .on_hover_cursor(egui::CursorIcon::Text)
+                    .on_hover_text("Bits cleared in the AND mask are set to these");
+                });
+            }
+            FC::ReadWriteRegisters => {
+                ui.horizontal(|ui| {
+                    ui.add_sized([80.0, 10.0], egui::Label::new("Write Offset:"));
+                    ui.add(
+                        egui::DragValue::new(&mut self.write_reg)
+                            .clamp_range(0..=u16::MAX)
+                            .speed(0.0),
+                    )
+                    .on_hover_cursor(egui::CursorIcon::Text);
+                    ui.add_sized([80.0, 10.0], egui::Label::new("Write Count:"));
+                    if ui
+                        .add(
+                            egui::DragValue::new(&mut self.write_count)
+                                .clamp_range(1..=121)
+                                .speed(0.0),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text)
+                        .lost_focus()
+                    {
+                        self.write_buffer = vec![0; (self.write_count * 2) as usize]
+                    }
+                });
+            }
+            FC::ReadFileRecord | FC::WriteFileRecord => {
+                if crate::file_record::draw_file_records(
+                    ui,
+                    &mut self.file_records,
+                    self.reg as usize + 1,
+                ) && self.function_code == FC::WriteFileRecord
+                {
+                    let length = crate::file_record::total_length(&self.file_records);
+                    self.write_buffer.resize(length * 2, 0);
+                }
+            }
+            FC::Custom => {
+                ui.horizontal(|ui| {
+                    ui.add_sized([80.0, 10.0], egui::Label::new("Code:"));
+                    ui.add(
+                        egui::DragValue::new(&mut self.raw_function_code)
+                            .clamp_range(1..=127)
+                            .speed(0.0),
+                    )
+                    .on_hover_cursor(egui::CursorIcon::Text);
+                    ui.add_sized([80.0, 10.0], egui::Label::new("Payload:"));
+                    ui.add_sized(
+                        [300.0, 10.0],
+                        egui::TextEdit::singleline(&mut self.raw_payload)
+                            .hint_text("Hex bytes after the code, like 00 01 A0")
+                            .font(egui::TextStyle::Monospace),
+                    );
+                });
+            }
+            FC::Diagnostics => crate::diagnostics::draw_diagnostics_request(
+                ui,
+                &mut self.sub_function,
+                &mut self.diagnostic_data,
+            ),
+            _ => (),
+        }
+        ui.horizontal(|ui| {
+            ui.add_sized([80.0, 10.0], egui::Label::new("Unit ID:"));
+            let unit_id = self.addressed_unit(device_unit_id);
+            egui::ComboBox::from_id_source("Unit ID")
+                .selected_text(match slaves.iter().find(|x| x.unit_id == unit_id) {
+                    Some(slave) if self.override_unit_id => slave.lable.to_owned(),
+                    _ if self.override_unit_id => "Own".to_owned(),
+                    _ => "Device".to_owned(),
+                })
+                .show_ui(ui, |ui| {
+                    if ui
+                        .selectable_label(
+                            !self.override_unit_id,
+                            format!("Device ({})", device_unit_id),
+                        )
+                        .clicked()
+                    {
+                        self.override_unit_id = false;
+                    }
+                    for slave in slaves {
+                        if ui
+                            .selectable_label(
+                                self.override_unit_id && self.unit_id == slave.unit_id,
+                                format!("{} ({})", slave.lable, slave.unit_id),
+                            )
+                            .clicked()
+                        {
+                            self.override_unit_id = true;
+                            self.unit_id = slave.unit_id;
+                        }
+                    }
+                });
+            let mut own_unit_id = unit_id;
+            if ui
+                .add(
+                    egui::DragValue::new(&mut own_unit_id)
+                        .clamp_range(0..=247)
+                        .speed(0.0),
+                )
+                .on_hover_cursor(egui::CursorIcon::Text)
+                .on_hover_text("0 broadcasts a write to all slaves of a serial line or gateway")
+                .changed()
+            {
+                self.override_unit_id = true;
+                self.unit_id = own_unit_id;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.poll_enabled, "Poll every");
+            ui.add(
+                egui::DragValue::new(&mut self.poll_interval_ms)
+                    .clamp_range(100..=3_600_000)
+                    .suffix(" ms")
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text)
+            .on_hover_text("From 100 ms up to one hour");
+        });
+        egui::CollapsingHeader::new("Raw Frames").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.add_sized([80.0, 10.0], egui::Label::new("Sent:"));
+                ui.monospace(self.raw_request.as_str());
+            });
+            ui.horizontal(|ui| {
+                ui.add_sized([80.0, 10.0], egui::Label::new("Received:"));
+                ui.monospace(self.raw_response.as_str());
+            });
+        });
         ui.separator();
 
+        if self.function_code == FC::Custom {
+            ui.horizontal(|ui| {
+                ui.add_sized([80.0, 10.0], egui::Label::new("Response:"));
+                ui.monospace(
+                    self.response_pdu
+                        .iter()
+                        .map(|b| format!("{:02X}", b))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.raw_decode, "Data from byte");
+                ui.add(
+                    egui::DragValue::new(&mut self.raw_data_offset)
+                        .clamp_range(0..=251)
+                        .speed(0.0),
+                )
+                .on_hover_cursor(egui::CursorIcon::Text)
+                .on_hover_text(
+                    "Counted after the function code, takes effect with the next response",
+                );
+            });
+            if !self.raw_decode {
+                ui.separator();
+                return;
+            }
+        }
+
+        if self.function_code.is_diagnostic() {
+            crate::diagnostics::draw_diagnostics_panel(ui, &self.response_pdu);
+            ui.separator();
+            return;
+        }
+
+        match self.function_code {
+            FC::ReadCoils | FC::ReadDiscreteInput => {
+                self.draw_read_coil_grid(ui);
+                ui.separator();
+                return;
+            }
+            FC::WriteCoil | FC::WriteCoils => {
+                self.draw_write_coil_grid(ui);
+                ui.separator();
+                return;
+            }
+            _ => (),
+        }
+
         ui.horizontal(|ui| {
-            // Radio button to set big or little endian ??
             ui.label("Data as ");
             egui::ComboBox::from_id_source("Dataview 1")
                 .selected_text(format!("{:?}", self.data_veiw1))
@@ -335,15 +1002,49 @@ impl QueryWrapper {
                         "32-bit Signed Integer",
                     );
                     ui.selectable_value(&mut self.data_veiw1, DataView::Float32bit, "32-bit Float");
+                    ui.selectable_value(
+                        &mut self.data_veiw1,
+                        DataView::Unsigned64bit,
+                        "64-bit Unsigned Integer",
+                    );
+                    ui.selectable_value(
+                        &mut self.data_veiw1,
+                        DataView::Signed64bit,
+                        "64-bit Signed Integer",
+                    );
+                    ui.selectable_value(&mut self.data_veiw1, DataView::Float64bit, "64-bit Float");
                     ui.selectable_value(&mut self.data_veiw1, DataView::Hexadecimal, "Hexadeciaml");
+                    ui.selectable_value(&mut self.data_veiw1, DataView::Text, "Text");
+                    ui.selectable_value(&mut self.data_veiw1, DataView::Bits, "Bits");
                 });
+            if self.data_veiw1 == DataView::Text {
+                self.text.draw_text_options(ui);
+                return;
+            }
+            // Hex and bits show registers one by one, the order doesn't apply
+            if !matches!(self.data_veiw1, DataView::Hexadecimal | DataView::Bits) {
+                egui::ComboBox::from_id_source("Endianness")
+                    .selected_text(self.endianness.name())
+                    .width(60.)
+                    .show_ui(ui, |ui| {
+                        for x in Endianness::ALL {
+                            ui.selectable_value(&mut self.endianness, x, x.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Byte order of values, A is the most significant byte");
+            }
 
             match self.function_code {
                 FC::ReadCoils
                 | FC::ReadDiscreteInput
                 | FC::ReadHoldingRegisters
-                | FC::ReadInputRegisters => {
-                    if self.data_veiw1 != DataView::Hexadecimal {
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
+                    if !matches!(self.data_veiw1, DataView::Hexadecimal | DataView::Bits) {
                         ui.label("Factor:");
                         ui.add(
                             egui::DragValue::new(&mut self.factor)
@@ -365,99 +1066,315 @@ impl QueryWrapper {
                 FC::WriteCoil
                 | FC::WriteCoils
                 | FC::WriteHoldingRegister
-                | FC::WriteHoldingRegisters => (),
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord
+                | FC::MaskWriteRegister => (),
+                FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
             }
         });
 
+        if self.function_code == FC::ReadWriteRegisters {
+            ui.label(format!("Written from Reg {}:", self.write_reg + 1));
+            ui.horizontal(|ui| match self.data_veiw1 {
+                DataView::Unsigned16bit => self.draw_write_data_grid_u16(ui),
+                DataView::Signed16bit => self.draw_write_data_grid_i16(ui),
+                DataView::Unsigned32bit => self.draw_write_data_grid_u32(ui),
+                DataView::Signed32bit => self.draw_write_data_grid_i32(ui),
+                DataView::Float32bit => self.draw_write_data_grid_f32(ui),
+                DataView::Unsigned64bit => self.draw_write_data_grid_u64(ui),
+                DataView::Signed64bit => self.draw_write_data_grid_i64(ui),
+                DataView::Float64bit => self.draw_write_data_grid_f64(ui),
+                DataView::Hexadecimal => self.draw_write_data_grid_hex(ui),
+                DataView::Text => self.draw_write_text(ui),
+                DataView::Bits => self.draw_write_data_grid_bits(ui),
+            });
+            ui.label(format!("Read from Reg {}:", self.reg + 1));
+        }
+
         ui.horizontal(|ui| match self.data_veiw1 {
             DataView::Unsigned16bit => match self.function_code {
                 FC::ReadCoils
                 | FC::ReadDiscreteInput
                 | FC::ReadHoldingRegisters
-                | FC::ReadInputRegisters => {
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
                     self.draw_read_data_grid_u16(ui);
                 }
                 FC::WriteCoil
                 | FC::WriteCoils
                 | FC::WriteHoldingRegister
-                | FC::WriteHoldingRegisters => {
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
                     self.draw_write_data_grid_u16(ui);
                 }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
             },
             DataView::Signed16bit => match self.function_code {
                 FC::ReadCoils
                 | FC::ReadDiscreteInput
                 | FC::ReadHoldingRegisters
-                | FC::ReadInputRegisters => {
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
                     self.draw_read_data_grid_i16(ui);
                 }
                 FC::WriteCoil
                 | FC::WriteCoils
                 | FC::WriteHoldingRegister
-                | FC::WriteHoldingRegisters => {
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
                     self.draw_write_data_grid_i16(ui);
                 }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
             },
             DataView::Unsigned32bit => match self.function_code {
                 FC::ReadCoils
                 | FC::ReadDiscreteInput
                 | FC::ReadHoldingRegisters
-                | FC::ReadInputRegisters => {
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
                     self.draw_read_data_grid_u32(ui);
                 }
                 FC::WriteCoil
                 | FC::WriteCoils
                 | FC::WriteHoldingRegister
-                | FC::WriteHoldingRegisters => {
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
                     self.draw_write_data_grid_u32(ui);
                 }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
             },
             DataView::Signed32bit => match self.function_code {
                 FC::ReadCoils
                 | FC::ReadDiscreteInput
                 | FC::ReadHoldingRegisters
-                | FC::ReadInputRegisters => {
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
                     self.draw_read_data_grid_i32(ui);
                 }
                 FC::WriteCoil
                 | FC::WriteCoils
                 | FC::WriteHoldingRegister
-                | FC::WriteHoldingRegisters => {
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
                     self.draw_write_data_grid_i32(ui);
                 }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
             },
             DataView::Float32bit => match self.function_code {
                 FC::ReadCoils
                 | FC::ReadDiscreteInput
                 | FC::ReadHoldingRegisters
-                | FC::ReadInputRegisters => {
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
                     self.draw_read_data_grid_f32(ui);
                 }
                 FC::WriteCoil
                 | FC::WriteCoils
                 | FC::WriteHoldingRegister
-                | FC::WriteHoldingRegisters => {
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
                     self.draw_write_data_grid_f32(ui);
                 }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
+            },
+            DataView::Unsigned64bit => match self.function_code {
+                FC::ReadCoils
+                | FC::ReadDiscreteInput
+                | FC::ReadHoldingRegisters
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
+                    self.draw_read_data_grid_u64(ui);
+                }
+                FC::WriteCoil
+                | FC::WriteCoils
+                | FC::WriteHoldingRegister
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
+                    self.draw_write_data_grid_u64(ui);
+                }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
+            },
+            DataView::Signed64bit => match self.function_code {
+                FC::ReadCoils
+                | FC::ReadDiscreteInput
+                | FC::ReadHoldingRegisters
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
+                    self.draw_read_data_grid_i64(ui);
+                }
+                FC::WriteCoil
+                | FC::WriteCoils
+                | FC::WriteHoldingRegister
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
+                    self.draw_write_data_grid_i64(ui);
+                }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
+            },
+            DataView::Float64bit => match self.function_code {
+                FC::ReadCoils
+                | FC::ReadDiscreteInput
+                | FC::ReadHoldingRegisters
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
+                    self.draw_read_data_grid_f64(ui);
+                }
+                FC::WriteCoil
+                | FC::WriteCoils
+                | FC::WriteHoldingRegister
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
+                    self.draw_write_data_grid_f64(ui);
+                }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
             },
             DataView::Hexadecimal => match self.function_code {
                 FC::ReadCoils
                 | FC::ReadDiscreteInput
                 | FC::ReadHoldingRegisters
-                | FC::ReadInputRegisters => {
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
                     self.draw_read_data_grid_hex(ui);
                 }
                 FC::WriteCoil
                 | FC::WriteCoils
                 | FC::WriteHoldingRegister
-                | FC::WriteHoldingRegisters => {
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
                     self.draw_write_data_grid_hex(ui);
                 }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
             },
-        });
-
-        ui.separator();
-    }
+            DataView::Text => match self.function_code {
+                FC::ReadCoils
+                | FC::ReadDiscreteInput
+                | FC::ReadHoldingRegisters
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
+                    self.draw_read_text(ui);
+                }
+                FC::WriteCoil
+                | FC::WriteCoils
+                | FC::WriteHoldingRegister
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
+                    self.draw_write_text(ui);
+                }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
+            },
+            DataView::Bits => match self.function_code {
+                FC::ReadCoils
+                | FC::ReadDiscreteInput
+                | FC::ReadHoldingRegisters
+                | FC::ReadInputRegisters
+                | FC::ReadWriteRegisters
+                | FC::ReadFileRecord
+                | FC::ReadFifoQueue
+                | FC::Custom => {
+                    self.draw_read_data_grid_bits(ui);
+                }
+                FC::WriteCoil
+                | FC::WriteCoils
+                | FC::WriteHoldingRegister
+                | FC::WriteHoldingRegisters
+                | FC::WriteFileRecord => {
+                    self.draw_write_data_grid_bits(ui);
+                }
+                FC::MaskWriteRegister
+                | FC::ReadExceptionStatus
+                | FC::Diagnostics
+                | FC::GetCommEventCounter
+                | FC::GetCommEventLog
+                | FC::ReportServerId => (),
+            },
+        });
+
+        ui.separator();
+    }
     ///Start to implementing generic function for drawing different datatypes, second guessed myself halfway
     ///because it might bee a  good idea to have the controle over each type for specific styling and parcing etc...
     //
@@ -510,7 +1427,7 @@ impl QueryWrapper {
             .striped(true)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.read_buffer.len()).step_by(2) {
+                for i in (0..self.read_buffer.len().saturating_sub(1)).step_by(2) {
                     if (i % 16) == 0 {
                         ui.end_row();
                     }
@@ -537,6 +1454,7 @@ impl QueryWrapper {
                                 self.factor,
                                 self.value_offsett,
                                 self.data_veiw1,
+                                self.endianness,
                             ));
                             ui.close_menu();
                         }
@@ -553,16 +1471,14 @@ impl QueryWrapper {
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.read_buffer.len()).step_by(2) {
+                for i in (0..self.read_buffer.len().saturating_sub(1)).step_by(2) {
                     if (i % 16) == 0 {
                         ui.end_row();
                     }
                     ui.add_sized(
                         [60.0, 20.0],
                         egui::Label::new(
-                            (u16::from_be_bytes([self.read_buffer[i], self.read_buffer[i + 1]])
-                                as f32
-                                * self.factor
+                            (u16::from_be_bytes(self.read_value(i)) as f32 * self.factor
                                 + self.value_offsett)
                                 .to_string(),
                         )
@@ -582,6 +1498,7 @@ impl QueryWrapper {
                                 self.factor,
                                 self.value_offsett,
                                 self.data_veiw1,
+                                self.endianness,
                             ));
                             ui.close_menu();
                         }
@@ -598,16 +1515,14 @@ impl QueryWrapper {
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.read_buffer.len()).step_by(2) {
+                for i in (0..self.read_buffer.len().saturating_sub(1)).step_by(2) {
                     if (i % 16) == 0 {
                         ui.end_row();
                     }
                     ui.add_sized(
                         [60.0, 20.0],
                         egui::Label::new(
-                            (i16::from_be_bytes([self.read_buffer[i], self.read_buffer[i + 1]])
-                                as f32
-                                * self.factor
+                            (i16::from_be_bytes(self.read_value(i)) as f32 * self.factor
                                 + self.value_offsett)
                                 .to_string(),
                         )
@@ -627,6 +1542,7 @@ impl QueryWrapper {
                                 self.factor,
                                 self.value_offsett,
                                 self.data_veiw1,
+                                self.endianness,
                             ));
                             ui.close_menu();
                         }
@@ -643,20 +1559,14 @@ impl QueryWrapper {
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.read_buffer.len() - 2).step_by(4) {
+                for i in (0..self.read_buffer.len().saturating_sub(3)).step_by(4) {
                     if (i % 32) == 0 {
                         ui.end_row();
                     }
                     ui.add_sized(
                         [60.0, 20.0],
                         egui::Label::new(
-                            (u32::from_be_bytes([
-                                self.read_buffer[i + 2],
-                                self.read_buffer[i + 3],
-                                self.read_buffer[i],
-                                self.read_buffer[i + 1],
-                            ]) as f32
-                                * self.factor
+                            (u32::from_be_bytes(self.read_value(i)) as f32 * self.factor
                                 + self.value_offsett)
                                 .to_string(),
                         )
@@ -676,6 +1586,7 @@ impl QueryWrapper {
                                 self.factor,
                                 self.value_offsett,
                                 self.data_veiw1,
+                                self.endianness,
                             ));
                             ui.close_menu();
                         }
@@ -692,20 +1603,14 @@ impl QueryWrapper {
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.read_buffer.len() - 2).step_by(4) {
+                for i in (0..self.read_buffer.len().saturating_sub(3)).step_by(4) {
                     if (i % 32) == 0 {
                         ui.end_row();
                     }
                     ui.add_sized(
                         [60.0, 20.0],
                         egui::Label::new(
-                            (i32::from_be_bytes([
-                                self.read_buffer[i + 2],
-                                self.read_buffer[i + 3],
-                                self.read_buffer[i],
-                                self.read_buffer[i + 1],
-                            ]) as f32
-                                * self.factor
+                            (i32::from_be_bytes(self.read_value(i)) as f32 * self.factor
                                 + self.value_offsett)
                                 .to_string(),
                         )
@@ -725,6 +1630,7 @@ impl QueryWrapper {
                                 self.factor,
                                 self.value_offsett,
                                 self.data_veiw1,
+                                self.endianness,
                             ));
                             ui.close_menu();
                         }
@@ -741,19 +1647,14 @@ impl QueryWrapper {
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.read_buffer.len() - 2).step_by(4) {
+                for i in (0..self.read_buffer.len().saturating_sub(3)).step_by(4) {
                     if (i % 32) == 0 {
                         ui.end_row();
                     }
                     ui.add_sized(
                         [60.0, 20.0],
                         egui::Label::new(
-                            (f32::from_be_bytes([
-                                self.read_buffer[i + 2],
-                                self.read_buffer[i + 3],
-                                self.read_buffer[i],
-                                self.read_buffer[i + 1],
-                            ]) * self.factor
+                            (f32::from_be_bytes(self.read_value(i)) * self.factor
                                 + self.value_offsett)
                                 .to_string(),
                         )
@@ -773,6 +1674,7 @@ impl QueryWrapper {
                                 self.factor,
                                 self.value_offsett,
                                 self.data_veiw1,
+                                self.endianness,
                             ));
                             ui.close_menu();
                         }
@@ -782,186 +1684,787 @@ impl QueryWrapper {
             });
     }
 
-    pub fn draw_write_data_grid_u16(&self, ui: &mut egui::Ui) {
+    pub fn draw_read_data_grid_u64(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("u64_grid_r")
+            .striped(true)
+            .max_col_width(120.)
+            .min_col_width(120.)
+            .show(ui, |ui| {
+                let mut reg_nr: u16 = self.reg + 1;
+                for i in (0..self.read_buffer.len().saturating_sub(7)).step_by(8) {
+                    if (i % 64) == 0 {
+                        ui.end_row();
+                    }
+                    ui.add_sized(
+                        [120.0, 20.0],
+                        egui::Label::new(scaled_text(
+                            u64::from_be_bytes(self.read_value(i)).into(),
+                            self.factor,
+                            self.value_offsett,
+                        ))
+                        .sense(egui::Sense::click()),
+                    )
+                    .on_hover_text(format!("Reg {}", reg_nr))
+                    .context_menu(|ui| {
+                        if ui.button("Watch").clicked() {
+                            self.watched_list.push(crate::watched::WatchedReg::new(
+                                format!(
+                                    "Reg {} as U64 * {} + {}",
+                                    reg_nr, self.factor, self.value_offsett
+                                )
+                                .to_owned(),
+                                "".to_owned(),
+                                i,
+                                self.factor,
+                                self.value_offsett,
+                                self.data_veiw1,
+                                self.endianness,
+                            ));
+                            ui.close_menu();
+                        }
+                    });
+                    reg_nr += 4;
+                }
+            });
+    }
+
+    pub fn draw_read_data_grid_i64(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("i64_grid_r")
+            .striped(true)
+            .max_col_width(120.)
+            .min_col_width(120.)
+            .show(ui, |ui| {
+                let mut reg_nr: u16 = self.reg + 1;
+                for i in (0..self.read_buffer.len().saturating_sub(7)).step_by(8) {
+                    if (i % 64) == 0 {
+                        ui.end_row();
+                    }
+                    ui.add_sized(
+                        [120.0, 20.0],
+                        egui::Label::new(scaled_text(
+                            i64::from_be_bytes(self.read_value(i)).into(),
+                            self.factor,
+                            self.value_offsett,
+                        ))
+                        .sense(egui::Sense::click()),
+                    )
+                    .on_hover_text(format!("Reg {}", reg_nr))
+                    .context_menu(|ui| {
+                        if ui.button("Watch").clicked() {
+                            self.watched_list.push(crate::watched::WatchedReg::new(
+                                format!(
+                                    "Reg {} as I64 * {} + {}",
+                                    reg_nr, self.factor, self.value_offsett
+                                )
+                                .to_owned(),
+                                "".to_owned(),
+                                i,
+                                self.factor,
+                                self.value_offsett,
+                                self.data_veiw1,
+                                self.endianness,
+                            ));
+                            ui.close_menu();
+                        }
+                    });
+                    reg_nr += 4;
+                }
+            });
+    }
+
+    pub fn draw_read_data_grid_f64(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("f64_grid_r")
+            .striped(true)
+            .max_col_width(120.)
+            .min_col_width(120.)
+            .show(ui, |ui| {
+                let mut reg_nr: u16 = self.reg + 1;
+                for i in (0..self.read_buffer.len().saturating_sub(7)).step_by(8) {
+                    if (i % 64) == 0 {
+                        ui.end_row();
+                    }
+                    ui.add_sized(
+                        [120.0, 20.0],
+                        egui::Label::new(
+                            (f64::from_be_bytes(self.read_value(i)) * self.factor as f64
+                                + self.value_offsett as f64)
+                                .to_string(),
+                        )
+                        .sense(egui::Sense::click()),
+                    )
+                    .on_hover_text(format!("Reg {}", reg_nr))
+                    .context_menu(|ui| {
+                        if ui.button("Watch").clicked() {
+                            self.watched_list.push(crate::watched::WatchedReg::new(
+                                format!(
+                                    "Reg {} as F64 * {} + {}",
+                                    reg_nr, self.factor, self.value_offsett
+                                )
+                                .to_owned(),
+                                "".to_owned(),
+                                i,
+                                self.factor,
+                                self.value_offsett,
+                                self.data_veiw1,
+                                self.endianness,
+                            ));
+                            ui.close_menu();
+                        }
+                    });
+                    reg_nr += 4;
+                }
+            });
+    }
+
+    pub fn draw_write_data_grid_u16(&mut self, ui: &mut egui::Ui) {
         egui::Grid::new("u16_grid_w")
             .striped(true)
             .max_col_width(60.)
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.write_buffer.len()).step_by(2) {
+                for i in (0..self.write_buffer.len().saturating_sub(1)).step_by(2) {
                     if (i % 16) == 0 {
                         ui.end_row();
                     }
-                    ui.add_sized(
-                        [60., 20.],
-                        egui::DragValue::new(unsafe {
-                            &mut std::slice::from_raw_parts_mut(
-                                self.write_buffer[i..i + 1].as_ptr() as *mut u16,
-                                2,
-                            )[0]
-                        })
-                        .clamp_range(u16::MIN..=u16::MAX)
-                        .speed(0.0),
-                    )
-                    .on_hover_cursor(egui::CursorIcon::Text)
-                    .on_hover_cursor(egui::CursorIcon::Text)
-                    .on_hover_text(format!("Reg {}", reg_nr));
+                    let mut value = u16::from_be_bytes(self.write_value(i));
+                    if ui
+                        .add_sized(
+                            [60., 20.],
+                            egui::DragValue::new(&mut value)
+                                .clamp_range(u16::MIN..=u16::MAX)
+                                .speed(0.0),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.set_write_value(i, value.to_be_bytes());
+                    }
                     //.context_menu(|ui| if ui.button("Watch").clicked() {});
                     reg_nr += 1;
                 }
             });
     }
 
-    pub fn draw_write_data_grid_i16(&self, ui: &mut egui::Ui) {
+    pub fn draw_write_data_grid_i16(&mut self, ui: &mut egui::Ui) {
         egui::Grid::new("i16_grid_w")
             .striped(true)
             .max_col_width(60.)
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.write_buffer.len()).step_by(2) {
+                for i in (0..self.write_buffer.len().saturating_sub(1)).step_by(2) {
                     if (i % 16) == 0 {
                         ui.end_row();
                     }
-                    ui.add_sized(
-                        [60., 20.],
-                        egui::DragValue::new(unsafe {
-                            &mut std::slice::from_raw_parts_mut(
-                                self.write_buffer[i..i + 1].as_ptr() as *mut i16,
-                                2,
-                            )[0]
-                        })
-                        .clamp_range(i16::MIN..=i16::MAX)
-                        .speed(0.0),
-                    )
-                    .on_hover_cursor(egui::CursorIcon::Text)
-                    .on_hover_text(format!("Reg {}", reg_nr));
+                    let mut value = i16::from_be_bytes(self.write_value(i));
+                    if ui
+                        .add_sized(
+                            [60., 20.],
+                            egui::DragValue::new(&mut value)
+                                .clamp_range(i16::MIN..=i16::MAX)
+                                .speed(0.0),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.set_write_value(i, value.to_be_bytes());
+                    }
                     //.context_menu(|ui| if ui.button("Watch").clicked() {});
                     reg_nr += 1;
                 }
             });
     }
 
-    pub fn draw_write_data_grid_u32(&self, ui: &mut egui::Ui) {
+    pub fn draw_write_data_grid_u32(&mut self, ui: &mut egui::Ui) {
         egui::Grid::new("u32_grid_w")
             .striped(true)
             .max_col_width(60.)
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.write_buffer.len()).step_by(4) {
+                for i in (0..self.write_buffer.len().saturating_sub(3)).step_by(4) {
                     if (i % 32) == 0 {
                         ui.end_row();
                     }
-                    ui.add_sized(
-                        [60., 20.],
-                        egui::DragValue::new(unsafe {
-                            &mut std::slice::from_raw_parts_mut(
-                                self.write_buffer[i..i + 3].as_ptr() as *mut u32,
-                                2,
-                            )[0]
-                        })
-                        .clamp_range(u32::MIN..=u32::MAX)
-                        .speed(0.0),
-                    )
-                    .on_hover_cursor(egui::CursorIcon::Text)
-                    .on_hover_text(format!("Reg {}", reg_nr));
+                    let mut value = u32::from_be_bytes(self.write_value(i));
+                    if ui
+                        .add_sized(
+                            [60., 20.],
+                            egui::DragValue::new(&mut value)
+                                .clamp_range(u32::MIN..=u32::MAX)
+                                .speed(0.0),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.set_write_value(i, value.to_be_bytes());
+                    }
                     //.context_menu(|ui| if ui.button("Watch").clicked() {});
                     reg_nr += 2;
                 }
             });
     }
 
-    pub fn draw_write_data_grid_i32(&self, ui: &mut egui::Ui) {
+    pub fn draw_write_data_grid_i32(&mut self, ui: &mut egui::Ui) {
         egui::Grid::new("i32_grid_w")
             .striped(true)
             .max_col_width(60.)
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.write_buffer.len()).step_by(4) {
+                for i in (0..self.write_buffer.len().saturating_sub(3)).step_by(4) {
                     if (i % 32) == 0 {
                         ui.end_row();
                     }
-                    ui.add_sized(
-                        [60., 20.],
-                        egui::DragValue::new(unsafe {
-                            &mut std::slice::from_raw_parts_mut(
-                                self.write_buffer[i..i + 3].as_ptr() as *mut i32,
-                                2,
-                            )[0]
-                        })
-                        .clamp_range(i32::MIN..=i32::MAX)
-                        .speed(0.0),
-                    )
-                    .on_hover_cursor(egui::CursorIcon::Text)
-                    .on_hover_text(format!("Reg {}", reg_nr));
+                    let mut value = i32::from_be_bytes(self.write_value(i));
+                    if ui
+                        .add_sized(
+                            [60., 20.],
+                            egui::DragValue::new(&mut value)
+                                .clamp_range(i32::MIN..=i32::MAX)
+                                .speed(0.0),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.set_write_value(i, value.to_be_bytes());
+                    }
                     //.context_menu(|ui| if ui.button("Watch").clicked() {});
                     reg_nr += 2;
                 }
             });
     }
 
-    pub fn draw_write_data_grid_f32(&self, ui: &mut egui::Ui) {
+    pub fn draw_write_data_grid_f32(&mut self, ui: &mut egui::Ui) {
         egui::Grid::new("f32_grid_w")
             .striped(true)
             .max_col_width(60.)
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.write_buffer.len()).step_by(4) {
+                for i in (0..self.write_buffer.len().saturating_sub(3)).step_by(4) {
                     if (i % 32) == 0 {
                         ui.end_row();
                     }
-                    ui.add_sized(
-                        [60., 20.],
-                        egui::DragValue::new(unsafe {
-                            &mut std::slice::from_raw_parts_mut(
-                                self.write_buffer[i..i + 3].as_ptr() as *mut f32,
-                                2,
-                            )[0]
-                        })
-                        .clamp_range(f32::MIN..=f32::MAX)
-                        .speed(0.0)
-                        .custom_formatter(|n, _| format!("{}", n)),
-                    )
-                    .on_hover_cursor(egui::CursorIcon::Text)
-                    .on_hover_text(format!("Reg {}", reg_nr));
+                    let mut value = f32::from_be_bytes(self.write_value(i));
+                    if ui
+                        .add_sized(
+                            [60., 20.],
+                            egui::DragValue::new(&mut value)
+                                .clamp_range(f32::MIN..=f32::MAX)
+                                .speed(0.0)
+                                .custom_formatter(|n, _| format!("{}", n)),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.set_write_value(i, value.to_be_bytes());
+                    }
                     //.context_menu(|ui| if ui.button("Watch").clicked() {});
                     reg_nr += 2;
                 }
             });
     }
 
-    pub fn draw_write_data_grid_hex(&self, ui: &mut egui::Ui) {
+    pub fn draw_write_data_grid_u64(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("u64_grid_w")
+            .striped(true)
+            .max_col_width(120.)
+            .min_col_width(120.)
+            .show(ui, |ui| {
+                let mut reg_nr: u16 = self.reg + 1;
+                for i in (0..self.write_buffer.len().saturating_sub(7)).step_by(8) {
+                    if (i % 64) == 0 {
+                        ui.end_row();
+                    }
+                    let mut value = u64::from_be_bytes(self.write_value(i));
+                    let id = ui.id().with(("u64_grid_w", i));
+                    if integer_edit(ui, id, &mut value)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.set_write_value(i, value.to_be_bytes());
+                    }
+                    //.context_menu(|ui| if ui.button("Watch").clicked() {});
+                    reg_nr += 4;
+                }
+            });
+    }
+
+    pub fn draw_write_data_grid_i64(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("i64_grid_w")
+            .striped(true)
+            .max_col_width(120.)
+            .min_col_width(120.)
+            .show(ui, |ui| {
+                let mut reg_nr: u16 = self.reg + 1;
+                for i in (0..self.write_buffer.len().saturating_sub(7)).step_by(8) {
+                    if (i % 64) == 0 {
+                        ui.end_row();
+                    }
+                    let mut value = i64::from_be_bytes(self.write_value(i));
+                    let id = ui.id().with(("i64_grid_w", i));
+                    if integer_edit(ui, id, &mut value)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.set_write_value(i, value.to_be_bytes());
+                    }
+                    //.context_menu(|ui| if ui.button("Watch").clicked() {});
+                    reg_nr += 4;
+                }
+            });
+    }
+
+    pub fn draw_write_data_grid_f64(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("f64_grid_w")
+            .striped(true)
+            .max_col_width(120.)
+            .min_col_width(120.)
+            .show(ui, |ui| {
+                let mut reg_nr: u16 = self.reg + 1;
+                for i in (0..self.write_buffer.len().saturating_sub(7)).step_by(8) {
+                    if (i % 64) == 0 {
+                        ui.end_row();
+                    }
+                    let mut value = f64::from_be_bytes(self.write_value(i));
+                    if ui
+                        .add_sized(
+                            [120., 20.],
+                            egui::DragValue::new(&mut value)
+                                .clamp_range(f64::MIN..=f64::MAX)
+                                .speed(0.0)
+                                .custom_formatter(|n, _| format!("{}", n)),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.set_write_value(i, value.to_be_bytes());
+                    }
+                    //.context_menu(|ui| if ui.button("Watch").clicked() {});
+                    reg_nr += 4;
+                }
+            });
+    }
+
+    pub fn draw_write_data_grid_hex(&mut self, ui: &mut egui::Ui) {
         egui::Grid::new("hex_grid_w")
             .striped(true)
             .max_col_width(60.)
             .min_col_width(60.)
             .show(ui, |ui| {
                 let mut reg_nr: u16 = self.reg + 1;
-                for i in (0..self.write_buffer.len()).step_by(2) {
+                for i in (0..self.write_buffer.len().saturating_sub(1)).step_by(2) {
                     if (i % 16) == 0 {
                         ui.end_row();
                     }
-                    ui.add_sized(
-                        [60., 20.],
-                        egui::DragValue::new(unsafe {
-                            &mut std::slice::from_raw_parts_mut(
-                                self.write_buffer[i..i + 1].as_ptr() as *mut u16,
-                                2,
-                            )[0]
-                        })
-                        .clamp_range(u16::MIN..=u16::MAX)
-                        .hexadecimal(4, false, true)
-                        .speed(0.0),
-                    )
-                    .on_hover_cursor(egui::CursorIcon::Text)
-                    .on_hover_text(format!("Reg {}", reg_nr));
+                    let mut value =
+                        u16::from_ne_bytes([self.write_buffer[i], self.write_buffer[i + 1]]);
+                    if ui
+                        .add_sized(
+                            [60., 20.],
+                            egui::DragValue::new(&mut value)
+                                .clamp_range(u16::MIN..=u16::MAX)
+                                .hexadecimal(4, false, true)
+                                .speed(0.0),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Text)
+                        .on_hover_text(format!("Reg {}", reg_nr))
+                        .changed()
+                    {
+                        self.write_buffer[i..i + 2].copy_from_slice(&value.to_ne_bytes());
+                    }
                     //.context_menu(|ui| if ui.button("Watch").clicked() {});
                     reg_nr += 1;
                 }
             });
     }
+
+    pub fn draw_read_coil_grid(&mut self, ui: &mut egui::Ui) {
+        let kind = match self.function_code {
+            FC::ReadDiscreteInput => "Input",
+            _ => "Coil",
+        };
+        egui::Grid::new("coil_grid_r")
+            .striped(true)
+            .min_col_width(20.)
+            .show(ui, |ui| {
+                for i in 0..self.read_coils.len() {
+                    let reg_nr = self.reg as usize + 1 + i;
+                    if (i % 16) == 0 {
+                        if i != 0 {
+                            ui.end_row();
+                        }
+                        ui.label(reg_nr.to_string());
+                    }
+                    self.draw_bit_cell(ui, i, self.read_coils[i], format!("{} {}", kind, reg_nr));
+                }
+            });
+        self.draw_named_bits(ui, self.read_coils.iter().copied());
+    }
+
+    pub fn draw_write_coil_grid(&mut self, ui: &mut egui::Ui) {
+        let count = match self.function_code {
+            FC::WriteCoil => 1,
+            _ => self.count as usize,
+        };
+        if self.write_coils.len() < count {
+            self.write_coils.resize(count, false);
+        }
+        egui::Grid::new("coil_grid_w")
+            .striped(true)
+            .min_col_width(20.)
+            .show(ui, |ui| {
+                for i in 0..count {
+                    let reg_nr = self.reg as usize + 1 + i;
+                    if (i % 16) == 0 {
+                        if i != 0 {
+                            ui.end_row();
+                        }
+                        ui.label(reg_nr.to_string());
+                    }
+                    let on = self.write_coils[i];
+                    if self
+                        .draw_bit_cell(ui, i, on, format!("Coil {}", reg_nr))
+                        .clicked()
+                    {
+                        self.write_coils[i] = !on;
+                    }
+                }
+            });
+    }
+
+    /// One row per register, bit 15 first
+    pub fn draw_read_data_grid_bits(&mut self, ui: &mut egui::Ui) {
+        ui.vertical(|ui| {
+            egui::Grid::new("bits_grid_r")
+                .striped(true)
+                .min_col_width(20.)
+                .show(ui, |ui| {
+                    for (n, i) in (0..self.read_buffer.len() / 2 * 2).step_by(2).enumerate() {
+                        let reg_nr = self.reg as usize + 1 + n;
+                        let word =
+                            u16::from_be_bytes([self.read_buffer[i], self.read_buffer[i + 1]]);
+                        ui.label(format!("Reg {}", reg_nr));
+                        for bit in (0..16).rev() {
+                            self.draw_bit_cell(
+                                ui,
+                                n * 16 + bit,
+                                word & (1 << bit) != 0,
+                                format!("Reg {} bit {}", reg_nr, bit),
+                            );
+                        }
+                        ui.end_row();
+                    }
+                });
+            let bits = self
+                .read_buffer
+                .chunks_exact(2)
+                .flat_map(|x| {
+                    let word = u16::from_be_bytes([x[0], x[1]]);
+                    (0..16).map(move |bit| word & (1 << bit) != 0)
+                })
+                .collect::<Vec<_>>();
+            self.draw_named_bits(ui, bits.into_iter());
+        });
+    }
+
+    pub fn draw_write_data_grid_bits(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("bits_grid_w")
+            .striped(true)
+            .min_col_width(20.)
+            .show(ui, |ui| {
+                for (n, i) in (0..self.write_buffer.len() / 2 * 2).step_by(2).enumerate() {
+                    let reg_nr = self.reg as usize + 1 + n;
+                    let word = u16::from_ne_bytes([self.write_buffer[i], self.write_buffer[i + 1]]);
+                    ui.label(format!("Reg {}", reg_nr));
+                    for bit in (0..16).rev() {
+                        if self
+                            .draw_bit_cell(
+                                ui,
+                                n * 16 + bit,
+                                word & (1 << bit) != 0,
+                                format!("Reg {} bit {}", reg_nr, bit),
+                            )
+                            .clicked()
+                        {
+                            self.write_buffer[i..i + 2]
+                                .copy_from_slice(&(word ^ (1 << bit)).to_ne_bytes());
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// A bit as 1 or 0, its name is edited in the context menu
+    fn draw_bit_cell(
+        &mut self,
+        ui: &mut egui::Ui,
+        index: usize,
+        on: bool,
+        address: String,
+    ) -> egui::Response {
+        let name = self.bit_names.get(index).cloned().unwrap_or_default();
+        let response = ui
+            .add_sized(
+                [20., 20.],
+                egui::SelectableLabel::new(on, if on { "1" } else { "0" }),
+            )
+            .on_hover_text(match name.is_empty() {
+                true => address,
+                false => format!("{}: {}", address, name),
+            });
+        response.clone().context_menu(|ui| {
+            let mut name = name;
+            ui.label("Name:");
+            if ui.text_edit_singleline(&mut name).changed() {
+                if self.bit_names.len() <= index {
+                    self.bit_names.resize(index + 1, String::new());
+                }
+                self.bit_names[index] = name;
+                while self.bit_names.last().is_some_and(|x| x.is_empty()) {
+                    self.bit_names.pop();
+                }
+            }
+        });
+        response
+    }
+
+    /// State of every bit that has a name
+    fn draw_named_bits(&self, ui: &mut egui::Ui, bits: impl Iterator<Item = bool>) {
+        for (name, on) in self.bit_names.iter().zip(bits) {
+            if !name.is_empty() {
+                ui.label(format!("{}: {}", name, if on { "ON" } else { "OFF" }));
+            }
+        }
+    }
+
+    pub fn draw_read_text(&mut self, ui: &mut egui::Ui) {
+        let span = self.text.span(self.read_buffer.len());
+        let reg_nr = self.reg as usize + 1 + span.start / 2;
+        ui.add(
+            egui::Label::new(
+                egui::RichText::new(self.text.decode(&self.read_buffer[span.clone()])).monospace(),
+            )
+            .sense(egui::Sense::click()),
+        )
+        .on_hover_text(format!("Reg {} to {}", reg_nr, reg_nr + span.len() / 2 - 1))
+        .context_menu(|ui| {
+            if ui.button("\u{1F441} Add to watched").clicked() {
+                let mut watched = crate::watched::WatchedReg::new(
+                    format!("Reg {} as Text", reg_nr),
+                    "".to_owned(),
+                    span.start,
+                    self.factor,
+                    self.value_offsett,
+                    self.data_veiw1,
+                    self.endianness,
+                );
+                watched.text = self.text.clone();
+                self.watched_list.push(watched);
+                ui.close_menu();
+            }
+        });
+    }
+
+    pub fn draw_write_text(&mut self, ui: &mut egui::Ui) {
+        let span = self.text.span(self.write_buffer.len());
+        let id = ui.make_persistent_id("text_write");
+        if !ui.memory(|x| x.has_focus(id)) {
+            let bytes = self.write_buffer[span.clone()]
+                .chunks_exact(2)
+                .flat_map(|x| u16::from_ne_bytes([x[0], x[1]]).to_be_bytes())
+                .collect::<Vec<_>>();
+            self.text_edit = self.text.decode(&bytes);
+        }
+        let reg_nr = self.reg as usize + 1 + span.start / 2;
+        if ui
+            .add_sized(
+                [300., 20.],
+                egui::TextEdit::singleline(&mut self.text_edit)
+                    .id(id)
+                    .font(egui::TextStyle::Monospace),
+            )
+            .on_hover_text(format!(
+                "Reg {} to {}, {} characters at most",
+                reg_nr,
+                reg_nr + span.len() / 2 - 1,
+                span.len()
+            ))
+            .changed()
+        {
+            let bytes = self.text.encode(&self.text_edit, span.len());
+            for (reg, x) in self.write_buffer[span]
+                .chunks_exact_mut(2)
+                .zip(bytes.chunks_exact(2))
+            {
+                reg.copy_from_slice(&u16::from_be_bytes([x[0], x[1]]).to_ne_bytes());
+            }
+        }
+    }
+
+    /// Value read from byte `i` on, most significant byte first
+    fn read_value<const N: usize>(&self, i: usize) -> [u8; N] {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&self.read_buffer[i..i + N]);
+        self.endianness.swap(bytes)
+    }
+
+    /// Value written from byte `i` on, most significant byte first.
+    /// The write buffer keeps its registers in native byte order.
+    fn write_value<const N: usize>(&self, i: usize) -> [u8; N] {
+        let mut bytes = [0; N];
+        for (x, reg) in bytes
+            .chunks_exact_mut(2)
+            .zip(self.write_buffer[i..i + N].chunks_exact(2))
+        {
+            x.copy_from_slice(&u16::from_ne_bytes([reg[0], reg[1]]).to_be_bytes());
+        }
+        self.endianness.swap(bytes)
+    }
+
+    fn set_write_value<const N: usize>(&mut self, i: usize, bytes: [u8; N]) {
+        let bytes = self.endianness.swap(bytes);
+        for (reg, x) in self.write_buffer[i..i + N]
+            .chunks_exact_mut(2)
+            .zip(bytes.chunks_exact(2))
+        {
+            reg.copy_from_slice(&u16::from_be_bytes([x[0], x[1]]).to_ne_bytes());
+        }
+    }
+}
+
+/// Bytes of a hex string, spaces between them are optional
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16).ok())
+        .collect()
+}
+
+/// Integer scaled by `factor` and `offset`, with as many decimals as they were typed
+/// with, so the binary rounding of the `f32` factor doesn't show
+pub fn scaled_text(raw: i128, factor: f32, offset: f32) -> String {
+    // f64 has 53 bits, only go through it when there is something to scale
+    if factor == 1.0 && offset == 0.0 {
+        return raw.to_string();
+    }
+    let decimals = |x: f32| x.to_string().split_once('.').map_or(0, |(_, x)| x.len());
+    format!(
+        "{:.*}",
+        decimals(factor).max(decimals(offset)),
+        raw as f64 * factor as f64 + offset as f64
+    )
+}
+
+/// Text field for a 64-bit integer, DragValue would round it through f64.
+/// The value changes once what is typed parses.
+pub fn integer_edit<T>(ui: &mut egui::Ui, id: egui::Id, value: &mut T) -> egui::Response
+where
+    T: std::fmt::Display + std::str::FromStr,
+{
+    // What is typed stays while the field has focus, it may not parse yet
+    let focused = ui.memory(|x| x.has_focus(id));
+    let mut text = ui
+        .data(|x| x.get_temp::<String>(id))
+        .filter(|_| focused)
+        .unwrap_or_else(|| value.to_string());
+    let mut response = ui.add_sized([120., 20.], egui::TextEdit::singleline(&mut text).id(id));
+    let edited = response.changed();
+    response.changed = false;
+    if edited {
+        if let Ok(x) = text.trim().parse() {
+            *value = x;
+            response.mark_changed();
+        }
+    }
+    if response.has_focus() {
+        ui.data_mut(|x| x.insert_temp(id, text));
+    } else {
+        ui.data_mut(|x| x.remove::<String>(id));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGS: [u8; 4] = [0xA, 0xB, 0xC, 0xD];
+
+    #[test]
+    fn swap_orders_two_registers() {
+        assert_eq!(Endianness::Abcd.swap(REGS), [0xA, 0xB, 0xC, 0xD]);
+        assert_eq!(Endianness::Cdab.swap(REGS), [0xC, 0xD, 0xA, 0xB]);
+        assert_eq!(Endianness::Badc.swap(REGS), [0xB, 0xA, 0xD, 0xC]);
+        assert_eq!(Endianness::Dcba.swap(REGS), [0xD, 0xC, 0xB, 0xA]);
+    }
+
+    #[test]
+    fn swap_orders_four_registers() {
+        let regs = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(Endianness::Abcd.swap(regs), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(Endianness::Cdab.swap(regs), [7, 8, 5, 6, 3, 4, 1, 2]);
+        assert_eq!(Endianness::Badc.swap(regs), [2, 1, 4, 3, 6, 5, 8, 7]);
+        assert_eq!(Endianness::Dcba.swap(regs), [8, 7, 6, 5, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn swap_of_one_register_only_swaps_bytes() {
+        assert_eq!(Endianness::Abcd.swap([0xA, 0xB]), [0xA, 0xB]);
+        assert_eq!(Endianness::Cdab.swap([0xA, 0xB]), [0xA, 0xB]);
+        assert_eq!(Endianness::Badc.swap([0xA, 0xB]), [0xB, 0xA]);
+        assert_eq!(Endianness::Dcba.swap([0xA, 0xB]), [0xB, 0xA]);
+    }
+
+    #[test]
+    fn swap_is_undone_by_itself() {
+        for order in Endianness::ALL {
+            assert_eq!(order.swap(order.swap(REGS)), REGS);
+        }
+    }
+
+    #[test]
+    fn scaled_text_takes_decimals_of_factor_and_offset() {
+        assert_eq!(scaled_text(1234, 0.1, 0.0), "123.4");
+        assert_eq!(scaled_text(12, 1.0, 0.25), "12.25");
+        assert_eq!(scaled_text(42, 1.0, 0.0), "42");
+    }
+
+    #[test]
+    fn scaled_text_of_unscaled_integers_is_exact() {
+        assert_eq!(scaled_text((1 << 53) + 1, 1.0, 0.0), "9007199254740993");
+        assert_eq!(
+            scaled_text(u64::MAX.into(), 1.0, 0.0),
+            "18446744073709551615"
+        );
+        assert_eq!(
+            scaled_text(i64::MIN.into(), 1.0, 0.0),
+            "-9223372036854775808"
+        );
+    }
+
+    #[test]
+    fn max_count_of_bit_reads_fits_the_framing() {
+        use rmodbus::ModbusProto;
+        assert_eq!(FC::ReadCoils.max_count(ModbusProto::TcpUdp), 1976);
+        assert_eq!(FC::ReadDiscreteInput.max_count(ModbusProto::TcpUdp), 1976);
+        assert_eq!(FC::ReadCoils.max_count(ModbusProto::Rtu), 2000);
+        assert_eq!(FC::ReadDiscreteInput.max_count(ModbusProto::Ascii), 2000);
+    }
 }
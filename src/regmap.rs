@@ -0,0 +1,402 @@
+use rmodbus::client::ModbusRequest;
+use rmodbus::ModbusProto;
+
+use crate::query::FC;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ProbeAction {
+    Nothing,
+    Start,
+    Cancel,
+    CreateQueries,
+}
+
+/// Finds which addresses of a device can be read, by reading whole blocks and
+/// halving the ones answered with ILLEGAL DATA ADDRESS until the gaps are found.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
+pub struct RegisterProbe {
+    pub function_code: FC,
+    pub first_reg: u16,
+    pub last_reg: u16,
+    pub block_size: u16,
+    /// Readable addresses as first and last of each contiguous range
+    #[serde(skip)]
+    pub readable: Vec<(u16, u16)>,
+    #[serde(skip)]
+    unreadable: u32,
+    #[serde(skip)]
+    unanswered: u32,
+    #[serde(skip)]
+    error: String,
+    /// Blocks still to read as first address and count, the next one is last
+    #[serde(skip)]
+    todo: Vec<(u16, u16)>,
+    #[serde(skip)]
+    pending: Option<(u64, u16, u16)>,
+    #[serde(skip)]
+    unit_id: u8,
+}
+
+impl Default for RegisterProbe {
+    fn default() -> Self {
+        Self {
+            function_code: FC::ReadHoldingRegisters,
+            first_reg: 0,
+            last_reg: 9999,
+            block_size: 123,
+            readable: vec![],
+            unreadable: 0,
+            unanswered: 0,
+            error: Default::default(),
+            todo: vec![],
+            pending: None,
+            unit_id: 0,
+        }
+    }
+}
+
+impl RegisterProbe {
+    pub fn running(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn start(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+        unit_id: u8,
+    ) {
+        self.cancel(worker);
+        self.plan(unit_id, settings.transport.proto());
+        // Nobody answers a broadcast, every block would pass as readable
+        if unit_id == 0 && settings.unit_0_is_broadcast() {
+            self.todo.clear();
+            self.error = "Unit 0 is broadcast on this link, probe a slave's own unit ID".to_owned();
+        }
+        self.send_next(worker, settings);
+    }
+
+    /// Splits the addresses into blocks no larger than one read can take
+    fn plan(&mut self, unit_id: u8, proto: ModbusProto) {
+        self.readable.clear();
+        self.unreadable = 0;
+        self.unanswered = 0;
+        self.error.clear();
+        self.unit_id = unit_id;
+
+        let max_count = self.function_code.max_count(proto);
+        let block_size = self.block_size.clamp(1, max_count) as u32;
+        let mut reg = self.first_reg as u32;
+        while reg <= self.last_reg as u32 {
+            let count = block_size.min(self.last_reg as u32 - reg + 1);
+            self.todo.push((reg as u16, count as u16));
+            reg += count;
+        }
+        self.todo.reverse();
+    }
+
+    pub fn cancel(&mut self, worker: &crate::worker::Worker) {
+        if let Some((id, _, _)) = self.pending.take() {
+            worker.cancel(id);
+        }
+        self.todo.clear();
+    }
+
+    pub fn waits_for(&self, reply: &crate::worker::Reply) -> bool {
+        matches!(self.pending, Some((id, _, _)) if id == reply.id)
+    }
+
+    pub fn receive(
+        &mut self,
+        reply: crate::worker::Reply,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+    ) {
+        let Some((_, reg, count)) = self.pending.take() else {
+            return;
+        };
+        self.answer(reg, count, reply.response, settings.transport.proto());
+        self.send_next(worker, settings);
+    }
+
+    /// Takes in how the block from `reg` on was answered
+    fn answer(
+        &mut self,
+        reg: u16,
+        count: u16,
+        response: Result<Vec<u8>, std::io::Error>,
+        proto: ModbusProto,
+    ) {
+        match response {
+            Ok(response) => {
+                match crate::transport::exception_code(&response, proto) {
+                    None => self.add_readable(reg, count),
+                    // Somewhere in the block is a gap, look at both halves
+                    Some(0x02) if count > 1 => {
+                        self.todo.push((reg + count / 2, count - count / 2));
+                        self.todo.push((reg, count / 2));
+                    }
+                    Some(0x01) => {
+                        self.error = "The device does not support this function code".to_owned();
+                        self.todo.clear();
+                    }
+                    Some(_) => self.unreadable += count as u32,
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => self.unanswered += count as u32,
+            Err(e) => {
+                self.error = e.to_string();
+                self.todo.clear();
+            }
+        }
+    }
+
+    fn add_readable(&mut self, reg: u16, count: u16) {
+        self.readable.push((reg, reg + (count - 1)));
+        self.readable.sort();
+        let mut merged: Vec<(u16, u16)> = vec![];
+        for (first, last) in self.readable.drain(..) {
+            match merged.last_mut() {
+                Some(range) if range.1 as u32 + 1 >= first as u32 => range.1 = range.1.max(last),
+                _ => merged.push((first, last)),
+            }
+        }
+        self.readable = merged;
+    }
+
+    fn send_next(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+    ) {
+        if let Some((reg, count, request)) = self.next_block(settings.transport.proto()) {
+            let id = worker.submit(settings.clone(), request);
+            self.pending = Some((id, reg, count));
+        }
+    }
+
+    /// The next block to read as first address, count and request
+    fn next_block(&mut self, proto: ModbusProto) -> Option<(u16, u16, Vec<u8>)> {
+        let (reg, count) = self.todo.pop()?;
+        let mut mreq = ModbusRequest::new(self.unit_id, proto);
+        let mut request = Vec::new();
+        let result = match self.function_code {
+            FC::ReadCoils => mreq.generate_get_coils(reg, count, &mut request),
+            FC::ReadDiscreteInput => mreq.generate_get_discretes(reg, count, &mut request),
+            FC::ReadInputRegisters => mreq.generate_get_inputs(reg, count, &mut request),
+            _ => mreq.generate_get_holdings(reg, count, &mut request),
+        };
+        match result {
+            Ok(_) => Some((reg, count, request)),
+            Err(e) => {
+                self.error = e.to_string();
+                self.todo.clear();
+                None
+            }
+        }
+    }
+
+    /// One query per readable range, ranges longer than a single read are split up
+    pub fn queries(&self, proto: ModbusProto) -> Vec<crate::query::QueryWrapper> {
+        let max_count = self.function_code.max_count(proto) as u32;
+        let mut queries = vec![];
+        for (first, last) in self.readable.iter() {
+            let mut reg = *first as u32;
+            while reg <= *last as u32 {
+                let count = max_count.min(*last as u32 - reg + 1);
+                let mut query = crate::query::QueryWrapper::new();
+                query.lable = format!("{:?} {}-{}", self.function_code, reg, reg + count - 1);
+                query.function_code = self.function_code;
+                query.reg = reg as u16;
+                query.count = count as u16;
+                queries.push(query);
+                reg += count;
+            }
+        }
+        queries
+    }
+
+    pub fn draw_register_probe(&mut self, ui: &mut egui::Ui, proto: ModbusProto) -> ProbeAction {
+        let mut action = ProbeAction::Nothing;
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Read With:"));
+            egui::ComboBox::from_id_source("Probe Function Code")
+                .selected_text(format!("{:?}", self.function_code))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.function_code, FC::ReadCoils, "FC1 Read Coils");
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadDiscreteInput,
+                        "FC2 Read Discrete Input",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadHoldingRegisters,
+                        "FC3 Read Holding Registers",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadInputRegisters,
+                        "FC4 Read Input Registers",
+                    );
+                });
+            ui.label("Block:");
+            let max_count = self.function_code.max_count(proto);
+            ui.add(
+                egui::DragValue::new(&mut self.block_size)
+                    .clamp_range(1..=max_count)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Addresses:"));
+            ui.add(
+                egui::DragValue::new(&mut self.first_reg)
+                    .clamp_range(0..=u16::MAX)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+            ui.label("to");
+            ui.add(
+                egui::DragValue::new(&mut self.last_reg)
+                    .clamp_range(self.first_reg..=u16::MAX)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+
+            if self.running() {
+                if ui.button("\u{23F9} Cancel").clicked() {
+                    action = ProbeAction::Cancel;
+                }
+            } else if ui.button("\u{23F5} Probe").clicked() {
+                action = ProbeAction::Start;
+            }
+        });
+
+        let total = self.last_reg.max(self.first_reg) as u32 - self.first_reg as u32 + 1;
+        let done = self
+            .readable
+            .iter()
+            .map(|(first, last)| (last - first) as u32 + 1)
+            .sum::<u32>()
+            + self.unreadable
+            + self.unanswered;
+        if self.running() || done > 0 {
+            ui.add(
+                egui::ProgressBar::new(done as f32 / total as f32)
+                    .text(format!("{} of {} addresses", done, total)),
+            );
+        }
+        if !self.error.is_empty() {
+            ui.label(self.error.as_str());
+        }
+
+        for (first, last) in self.readable.iter() {
+            ui.label(format!(
+                "{} to {} readable, {} addresses",
+                first,
+                last,
+                (last - first) as u32 + 1
+            ));
+        }
+        if self.unreadable > 0 {
+            ui.label(format!("{} addresses not readable", self.unreadable));
+        }
+        if self.unanswered > 0 {
+            ui.label(format!("{} addresses not answered", self.unanswered));
+        }
+
+        if !self.running() && !self.readable.is_empty() && ui.button("Create Queries").clicked() {
+            action = ProbeAction::CreateQueries;
+        }
+
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers reads like a device with registers 0 to 9 and 20 to 299,
+    /// a read of any other address gets ILLEGAL DATA ADDRESS
+    fn device(request: &[u8]) -> Vec<u8> {
+        let reg = u16::from_be_bytes([request[8], request[9]]) as u32;
+        let count = u16::from_be_bytes([request[10], request[11]]) as u32;
+        let mut response = request[..8].to_vec();
+        if (reg..reg + count).all(|x| x <= 9 || (20..=299).contains(&x)) {
+            response.push((2 * count) as u8);
+            response.resize(response.len() + 2 * count as usize, 0);
+        } else {
+            response[7] |= 0x80;
+            response.push(0x02);
+        }
+        let len = response.len() as u16 - 6;
+        response[4..6].copy_from_slice(&len.to_be_bytes());
+        response
+    }
+
+    /// Runs the probe against the device, gives the counts it asked for
+    fn run(probe: &mut RegisterProbe, proto: ModbusProto) -> Vec<u16> {
+        probe.plan(1, proto);
+        let mut counts = vec![];
+        while let Some((reg, count, request)) = probe.next_block(proto) {
+            counts.push(count);
+            probe.answer(reg, count, Ok(device(&request)), proto);
+        }
+        counts
+    }
+
+    #[test]
+    fn probe_bisects_around_the_gaps() {
+        let mut probe = RegisterProbe {
+            first_reg: 0,
+            last_reg: 399,
+            ..Default::default()
+        };
+        run(&mut probe, ModbusProto::TcpUdp);
+        assert_eq!(probe.readable, [(0, 9), (20, 299)]);
+        assert_eq!(probe.unreadable, 110);
+        assert_eq!(probe.unanswered, 0);
+        assert!(probe.error.is_empty());
+    }
+
+    #[test]
+    fn probe_blocks_fit_the_framing() {
+        let mut probe = RegisterProbe {
+            first_reg: 20,
+            last_reg: 299,
+            block_size: 125,
+            ..Default::default()
+        };
+        assert_eq!(run(&mut probe, ModbusProto::TcpUdp), [123, 123, 34]);
+        let counts = probe
+            .queries(ModbusProto::TcpUdp)
+            .iter()
+            .map(|x| x.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [123, 123, 34]);
+        assert!(counts
+            .iter()
+            .all(|x| *x <= FC::ReadHoldingRegisters.max_count(ModbusProto::TcpUdp)));
+    }
+
+    #[test]
+    fn probe_of_coils_fits_an_mbap_response() {
+        let probe = RegisterProbe {
+            function_code: FC::ReadCoils,
+            readable: vec![(0, 3999)],
+            ..Default::default()
+        };
+        let counts = probe
+            .queries(ModbusProto::TcpUdp)
+            .iter()
+            .map(|x| x.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [1976, 1976, 48]);
+    }
+}
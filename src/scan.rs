@@ -0,0 +1,261 @@
+use rmodbus::client::ModbusRequest;
+
+use crate::query::FC;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ScanAction {
+    Nothing,
+    Start,
+    Cancel,
+    AddLiveUnits,
+}
+
+pub enum UnitOutcome {
+    Responding,
+    Exception(u8),
+    Timeout,
+    Error(String),
+}
+
+pub struct UnitResult {
+    pub unit_id: u8,
+    pub outcome: UnitOutcome,
+}
+
+/// Asks every unit ID in a range for one register or coil, one unit at a time,
+/// so polling of the device carries on in between.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
+pub struct UnitScan {
+    pub function_code: FC,
+    pub reg: u16,
+    pub first_unit: u8,
+    pub last_unit: u8,
+    #[serde(skip)]
+    pub results: Vec<UnitResult>,
+    #[serde(skip)]
+    next_unit: Option<u8>,
+    #[serde(skip)]
+    pending: Option<(u64, u8)>,
+}
+
+impl Default for UnitScan {
+    fn default() -> Self {
+        Self {
+            function_code: FC::ReadHoldingRegisters,
+            reg: 0,
+            first_unit: 1,
+            last_unit: 247,
+            results: vec![],
+            next_unit: None,
+            pending: None,
+        }
+    }
+}
+
+impl UnitScan {
+    pub fn running(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn start(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+    ) {
+        self.cancel(worker);
+        self.results.clear();
+        self.next_unit = Some(self.first_unit);
+        self.send_next(worker, settings);
+    }
+
+    pub fn cancel(&mut self, worker: &crate::worker::Worker) {
+        if let Some((id, _)) = self.pending.take() {
+            worker.cancel(id);
+        }
+        self.next_unit = None;
+    }
+
+    pub fn waits_for(&self, reply: &crate::worker::Reply) -> bool {
+        matches!(self.pending, Some((id, _)) if id == reply.id)
+    }
+
+    pub fn receive(
+        &mut self,
+        reply: crate::worker::Reply,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+    ) {
+        if let Some((_, unit_id)) = self.pending.take() {
+            let outcome = match reply.response {
+                Ok(response) => {
+                    match crate::transport::exception_code(&response, settings.transport.proto()) {
+                        Some(code) => UnitOutcome::Exception(code),
+                        None => UnitOutcome::Responding,
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => UnitOutcome::Timeout,
+                Err(e) => UnitOutcome::Error(e.to_string()),
+            };
+            self.results.push(UnitResult { unit_id, outcome });
+            self.send_next(worker, settings);
+        }
+    }
+
+    /// Units that answered, an exception still means someone is there
+    pub fn live_units(&self) -> Vec<u8> {
+        self.results
+            .iter()
+            .filter(|x| {
+                matches!(
+                    x.outcome,
+                    UnitOutcome::Responding | UnitOutcome::Exception(_)
+                )
+            })
+            .map(|x| x.unit_id)
+            .collect()
+    }
+
+    fn send_next(
+        &mut self,
+        worker: &mut crate::worker::Worker,
+        settings: &crate::transport::LinkSettings,
+    ) {
+        while let Some(unit_id) = self.next_unit {
+            self.next_unit = if unit_id < self.last_unit {
+                Some(unit_id + 1)
+            } else {
+                None
+            };
+
+            let mut mreq = ModbusRequest::new(unit_id, settings.transport.proto());
+            let mut request = Vec::new();
+            let result = match self.function_code {
+                FC::ReadCoils => mreq.generate_get_coils(self.reg, 1, &mut request),
+                FC::ReadDiscreteInput => mreq.generate_get_discretes(self.reg, 1, &mut request),
+                FC::ReadInputRegisters => mreq.generate_get_inputs(self.reg, 1, &mut request),
+                _ => mreq.generate_get_holdings(self.reg, 1, &mut request),
+            };
+            match result {
+                Ok(_) => {
+                    let id = worker.submit(settings.clone(), request);
+                    self.pending = Some((id, unit_id));
+                    return;
+                }
+                Err(e) => self.results.push(UnitResult {
+                    unit_id,
+                    outcome: UnitOutcome::Error(e.to_string()),
+                }),
+            }
+        }
+    }
+
+    pub fn draw_unit_scan(&mut self, ui: &mut egui::Ui) -> ScanAction {
+        let mut action = ScanAction::Nothing;
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Probe With:"));
+            egui::ComboBox::from_id_source("Scan Function Code")
+                .selected_text(format!("{:?}", self.function_code))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.function_code, FC::ReadCoils, "FC1 Read Coils");
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadDiscreteInput,
+                        "FC2 Read Discrete Input",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadHoldingRegisters,
+                        "FC3 Read Holding Registers",
+                    );
+                    ui.selectable_value(
+                        &mut self.function_code,
+                        FC::ReadInputRegisters,
+                        "FC4 Read Input Registers",
+                    );
+                });
+            ui.label("Offset:");
+            ui.add(
+                egui::DragValue::new(&mut self.reg)
+                    .clamp_range(0..=u16::MAX)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Unit IDs:"));
+            ui.add(
+                egui::DragValue::new(&mut self.first_unit)
+                    .clamp_range(1..=247)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+            ui.label("to");
+            ui.add(
+                egui::DragValue::new(&mut self.last_unit)
+                    .clamp_range(self.first_unit..=247)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+
+            if self.running() {
+                if ui.button("\u{23F9} Cancel").clicked() {
+                    action = ScanAction::Cancel;
+                }
+            } else if ui.button("\u{23F5} Scan").clicked() {
+                action = ScanAction::Start;
+            }
+        });
+
+        let total = (self.last_unit.max(self.first_unit) - self.first_unit) as usize + 1;
+        if self.running() || !self.results.is_empty() {
+            ui.add(
+                egui::ProgressBar::new(self.results.len() as f32 / total as f32).text(format!(
+                    "{} of {} units",
+                    self.results.len(),
+                    total
+                )),
+            );
+        }
+
+        let timeouts = self
+            .results
+            .iter()
+            .filter(|x| matches!(x.outcome, UnitOutcome::Timeout))
+            .count();
+        for x in self
+            .results
+            .iter()
+            .filter(|x| !matches!(x.outcome, UnitOutcome::Timeout))
+        {
+            ui.horizontal(|ui| {
+                ui.add_sized(
+                    [100.0, 10.0],
+                    egui::Label::new(format!("Unit {}", x.unit_id)),
+                );
+                match &x.outcome {
+                    UnitOutcome::Responding => ui.label("Responding"),
+                    UnitOutcome::Exception(code) => ui.label(format!(
+                        "Exception {:02X}, {}",
+                        code,
+                        crate::transport::exception_text(*code)
+                    )),
+                    UnitOutcome::Error(e) => ui.label(e.as_str()),
+                    UnitOutcome::Timeout => ui.label("Timed out"),
+                };
+            });
+        }
+        if timeouts > 0 {
+            ui.label(format!("{} units did not answer", timeouts));
+        }
+
+        if !self.running() && !self.live_units().is_empty() && ui.button("Add Live Units").clicked()
+        {
+            action = ScanAction::AddLiveUnits;
+        }
+
+        action
+    }
+}
@@ -0,0 +1,160 @@
+/// Registers read or written as packed text, two characters to a register
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+pub struct TextView {
+    /// First register of the text, counted from the first register of the query
+    pub offset: u16,
+    /// Registers taken by the text, 0 takes them all up to the end
+    pub length: u16,
+    /// Low byte first, the first character is in the high byte otherwise
+    pub byte_swap: bool,
+    /// The text ends at the first zero byte, and is padded with zeros when written
+    pub null_terminated: bool,
+    pub trim: bool,
+}
+
+impl Default for TextView {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            length: 0,
+            byte_swap: false,
+            null_terminated: true,
+            trim: true,
+        }
+    }
+}
+
+impl TextView {
+    /// Bytes of the registers the text takes, in a buffer of `buffer_len` bytes
+    pub fn span(&self, buffer_len: usize) -> std::ops::Range<usize> {
+        let start = (2 * self.offset as usize).min(buffer_len & !1);
+        let end = match self.length {
+            0 => buffer_len & !1,
+            length => (start + 2 * length as usize).min(buffer_len & !1),
+        };
+        start..end
+    }
+
+    /// Text of register bytes as they are sent, high byte first
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let mut bytes = bytes.to_vec();
+        if self.byte_swap {
+            bytes.chunks_exact_mut(2).for_each(|x| x.swap(0, 1));
+        }
+        if self.null_terminated {
+            if let Some(end) = bytes.iter().position(|x| *x == 0) {
+                bytes.truncate(end);
+            }
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        match self.trim {
+            true => text.trim_matches(|c: char| c.is_whitespace() || c == '\0'),
+            false => &text,
+        }
+        .to_owned()
+    }
+
+    /// Register bytes of `text`, cut or padded to `len` bytes
+    pub fn encode(&self, text: &str, len: usize) -> Vec<u8> {
+        let mut end = text.len().min(len);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut bytes = text.as_bytes()[..end].to_vec();
+        let pad = match self.null_terminated {
+            true => 0,
+            false => b' ',
+        };
+        bytes.resize(len, pad);
+        if self.byte_swap {
+            bytes.chunks_exact_mut(2).for_each(|x| x.swap(0, 1));
+        }
+        bytes
+    }
+
+    pub fn draw_text_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("From Reg Offset:");
+        ui.add(
+            egui::DragValue::new(&mut self.offset)
+                .clamp_range(0..=u16::MAX)
+                .speed(0.0),
+        )
+        .on_hover_cursor(egui::CursorIcon::Text);
+        ui.label("Regs:");
+        ui.add(
+            egui::DragValue::new(&mut self.length)
+                .clamp_range(0..=u16::MAX)
+                .speed(0.0),
+        )
+        .on_hover_cursor(egui::CursorIcon::Text)
+        .on_hover_text("0 takes the registers up to the end");
+        ui.checkbox(&mut self.byte_swap, "Swap Bytes")
+            .on_hover_text("The first character is in the low byte of a register");
+        ui.checkbox(&mut self.null_terminated, "Null Terminated");
+        ui.checkbox(&mut self.trim, "Trim");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_high_byte_first() {
+        let text = TextView::default();
+        let bytes = text.encode("ABC", 6);
+        assert_eq!(bytes, b"ABC\0\0\0");
+        assert_eq!(text.decode(&bytes), "ABC");
+    }
+
+    #[test]
+    fn round_trip_byte_swapped() {
+        let text = TextView {
+            byte_swap: true,
+            ..Default::default()
+        };
+        let bytes = text.encode("ABC", 4);
+        assert_eq!(bytes, b"BA\0C");
+        assert_eq!(text.decode(&bytes), "ABC");
+    }
+
+    #[test]
+    fn space_padded_without_null_termination() {
+        let text = TextView {
+            null_terminated: false,
+            trim: false,
+            ..Default::default()
+        };
+        let bytes = text.encode("AB", 4);
+        assert_eq!(bytes, b"AB  ");
+        assert_eq!(text.decode(&bytes), "AB  ");
+        let trimmed = TextView { trim: true, ..text };
+        assert_eq!(trimmed.decode(&bytes), "AB");
+    }
+
+    #[test]
+    fn decode_stops_at_null() {
+        assert_eq!(TextView::default().decode(b"AB\0D"), "AB");
+    }
+
+    #[test]
+    fn encode_cuts_on_char_boundary() {
+        // The euro sign takes 3 bytes and doesn't fit after "AB" in 4
+        let text = TextView::default();
+        assert_eq!(text.encode("AB\u{20ac}", 4), b"AB\0\0");
+        assert_eq!(text.decode(&text.encode("AB\u{20ac}", 6)), "AB\u{20ac}");
+    }
+
+    #[test]
+    fn span_is_clamped_to_whole_registers() {
+        let text = TextView {
+            offset: 1,
+            length: 2,
+            ..Default::default()
+        };
+        assert_eq!(text.span(10), 2..6);
+        assert_eq!(text.span(5), 2..4);
+        assert_eq!(text.span(1), 0..0);
+    }
+}
@@ -0,0 +1,207 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::TcpStream;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
+pub struct TlsSettings {
+    pub ca_path: String,
+    pub cert_path: String,
+    pub key_path: String,
+    /// Name the server certificate has to be issued for, the IP address is used when empty
+    pub server_name: String,
+    pub verify_certificate: bool,
+    pub verify_server_name: bool,
+}
+
+impl Default for TlsSettings {
+    fn default() -> Self {
+        Self {
+            ca_path: Default::default(),
+            cert_path: Default::default(),
+            key_path: Default::default(),
+            server_name: Default::default(),
+            verify_certificate: true,
+            verify_server_name: true,
+        }
+    }
+}
+
+impl TlsSettings {
+    pub fn draw_tls_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("CA Bundle:"));
+            ui.add_sized(
+                [200.0, 10.0],
+                egui::TextEdit::singleline(&mut self.ca_path).hint_text("Path to PEM file"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Client Cert:"));
+            ui.add_sized(
+                [200.0, 10.0],
+                egui::TextEdit::singleline(&mut self.cert_path).hint_text("Path to PEM file"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Client Key:"));
+            ui.add_sized(
+                [200.0, 10.0],
+                egui::TextEdit::singleline(&mut self.key_path).hint_text("Path to PEM file"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Server Name:"));
+            ui.add_sized(
+                [200.0, 10.0],
+                egui::TextEdit::singleline(&mut self.server_name).hint_text("Same as IP"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_space(104.0);
+            ui.checkbox(&mut self.verify_certificate, "Verify server certificate");
+            ui.add_enabled(
+                self.verify_certificate,
+                egui::Checkbox::new(&mut self.verify_server_name, "Verify server name"),
+            );
+        });
+    }
+
+    /// Wraps a connected socket in TLS and runs the handshake, so certificate
+    /// problems show up when connecting rather than on the first request.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn connect(
+        &self,
+        mut tcp_stream: TcpStream,
+        ip: &str,
+    ) -> Result<rustls::StreamOwned<rustls::ClientConnection, TcpStream>, std::io::Error> {
+        let mut roots = rustls::RootCertStore::empty();
+        if !self.ca_path.is_empty() {
+            for cert in load_certificates(&self.ca_path)? {
+                roots.add(&cert).map_err(invalid_data)?;
+            }
+        } else if self.verify_certificate {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "A CA bundle is needed to verify the server certificate",
+            ));
+        }
+
+        let builder = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(Verifier {
+                inner: rustls::client::WebPkiVerifier::new(roots, None),
+                verify_certificate: self.verify_certificate,
+                verify_server_name: self.verify_server_name,
+            }));
+        let config = if self.cert_path.is_empty() {
+            builder.with_no_client_auth()
+        } else {
+            builder
+                .with_client_auth_cert(
+                    load_certificates(&self.cert_path)?,
+                    load_private_key(&self.key_path)?,
+                )
+                .map_err(invalid_data)?
+        };
+
+        let server_name = if self.server_name.is_empty() {
+            ip
+        } else {
+            self.server_name.as_str()
+        };
+        let mut tls_connection = rustls::ClientConnection::new(
+            Arc::new(config),
+            rustls::ServerName::try_from(server_name).map_err(invalid_data)?,
+        )
+        .map_err(invalid_data)?;
+
+        while tls_connection.is_handshaking() {
+            tls_connection.complete_io(&mut tcp_stream)?;
+        }
+        Ok(rustls::StreamOwned::new(tls_connection, tcp_stream))
+    }
+}
+
+/// Certificate verification that can be relaxed for devices with self made certificates
+#[cfg(not(target_arch = "wasm32"))]
+struct Verifier {
+    inner: rustls::client::WebPkiVerifier,
+    verify_certificate: bool,
+    verify_server_name: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl rustls::client::ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        if !self.verify_certificate {
+            return Ok(rustls::client::ServerCertVerified::assertion());
+        }
+        match self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        ) {
+            Err(rustls::Error::InvalidCertificate(rustls::CertificateError::NotValidForName))
+                if !self.verify_server_name =>
+            {
+                Ok(rustls::client::ServerCertVerified::assertion())
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn invalid_data<E: std::error::Error + Send + Sync + 'static>(e: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn open_pem(path: &str) -> Result<std::io::BufReader<std::fs::File>, std::io::Error> {
+    match std::fs::File::open(path) {
+        Ok(file) => Ok(std::io::BufReader::new(file)),
+        Err(e) => Err(std::io::Error::new(e.kind(), format!("{}: {}", path, e))),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_certificates(path: &str) -> Result<Vec<rustls::Certificate>, std::io::Error> {
+    Ok(rustls_pemfile::certs(&mut open_pem(path)?)?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_private_key(path: &str) -> Result<rustls::PrivateKey, std::io::Error> {
+    let mut reader = open_pem(path)?;
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(rustls::PrivateKey(key)),
+            _ => (),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{}: no private key found", path),
+    ))
+}
@@ -0,0 +1,1125 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use rmodbus::{generate_ascii_frame, guess_response_frame_len, parse_ascii_frame, ModbusProto};
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Clone, Copy)]
+pub enum Transport {
+    Tcp,
+    Udp,
+    RtuSerial,
+    RtuOverTcp,
+    AsciiSerial,
+    AsciiOverTcp,
+    Tls,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Clone, Copy)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Clone, Copy)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
+pub struct SerialSettings {
+    pub port_name: String,
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub frame_delay_ms: u64,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            port_name: Default::default(),
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            frame_delay_ms: 0,
+        }
+    }
+}
+
+impl SerialSettings {
+    /// Silence needed between two frames, never shorter than 3.5 character times.
+    pub fn frame_delay(&self) -> Duration {
+        // One character is 11 bits on the wire, above 19200 baud the spec fixes t3.5 to 1.75 ms
+        let t35 = if self.baud_rate > 19200 {
+            Duration::from_micros(1750)
+        } else {
+            Duration::from_micros(38_500_000 / self.baud_rate.max(1) as u64)
+        };
+        Duration::from_millis(self.frame_delay_ms).max(t35)
+    }
+
+    pub fn draw_serial_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Serial Port:"));
+            ui.add_sized(
+                [200.0, 10.0],
+                egui::TextEdit::singleline(&mut self.port_name).hint_text("/dev/ttyUSB0 or COM1"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Baud Rate:"));
+            egui::ComboBox::from_id_source("Baud Rate")
+                .selected_text(self.baud_rate.to_string())
+                .show_ui(ui, |ui| {
+                    for baud in [1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200] {
+                        ui.selectable_value(&mut self.baud_rate, baud, baud.to_string());
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Data Bits:"));
+            egui::ComboBox::from_id_source("Data Bits")
+                .selected_text(self.data_bits.to_string())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.data_bits, 7, "7");
+                    ui.selectable_value(&mut self.data_bits, 8, "8");
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Parity:"));
+            egui::ComboBox::from_id_source("Parity")
+                .selected_text(format!("{:?}", self.parity))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.parity, Parity::None, "None");
+                    ui.selectable_value(&mut self.parity, Parity::Odd, "Odd");
+                    ui.selectable_value(&mut self.parity, Parity::Even, "Even");
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Stop Bits:"));
+            egui::ComboBox::from_id_source("Stop Bits")
+                .selected_text(format!("{:?}", self.stop_bits))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.stop_bits, StopBits::One, "One");
+                    ui.selectable_value(&mut self.stop_bits, StopBits::Two, "Two");
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Frame Delay ms:"));
+            ui.add(
+                egui::DragValue::new(&mut self.frame_delay_ms)
+                    .clamp_range(0..=1000)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text)
+            .on_hover_text("Silence before each request, 0 uses 3.5 character times");
+        });
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+#[serde(default)] // if we add new fields, give them default values when deserializing old state
+pub struct TimingSettings {
+    pub connect_timeout_ms: u64,
+    pub response_timeout_ms: u64,
+    /// Extra attempts after a request failed or timed out
+    pub retries: u32,
+    /// Least time between the end of one request and the start of the next
+    pub request_gap_ms: u64,
+}
+
+impl Default for TimingSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout_ms: 1000,
+            response_timeout_ms: 1000,
+            retries: 0,
+            request_gap_ms: 0,
+        }
+    }
+}
+
+impl TimingSettings {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms.max(1))
+    }
+
+    pub fn response_timeout(&self) -> Duration {
+        Duration::from_millis(self.response_timeout_ms.max(1))
+    }
+
+    pub fn request_gap(&self) -> Duration {
+        Duration::from_millis(self.request_gap_ms)
+    }
+
+    pub fn draw_timing_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Connect Timeout:"));
+            ui.add(
+                egui::DragValue::new(&mut self.connect_timeout_ms)
+                    .clamp_range(1..=60000)
+                    .suffix(" ms")
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Response Timeout:"));
+            ui.add(
+                egui::DragValue::new(&mut self.response_timeout_ms)
+                    .clamp_range(1..=60000)
+                    .suffix(" ms")
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text);
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Retries:"));
+            ui.add(
+                egui::DragValue::new(&mut self.retries)
+                    .clamp_range(0..=10)
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text)
+            .on_hover_text("Extra attempts after a failed or timed out request");
+        });
+
+        ui.horizontal(|ui| {
+            ui.add_sized([100.0, 10.0], egui::Label::new("Request Gap:"));
+            ui.add(
+                egui::DragValue::new(&mut self.request_gap_ms)
+                    .clamp_range(0..=60000)
+                    .suffix(" ms")
+                    .speed(0.0),
+            )
+            .on_hover_cursor(egui::CursorIcon::Text)
+            .on_hover_text("Least time between two requests to the device");
+        });
+    }
+}
+
+impl Transport {
+    /// Framing used on the wire, decides how requests are built and parsed
+    pub fn proto(&self) -> ModbusProto {
+        match self {
+            Transport::Tcp | Transport::Udp | Transport::Tls => ModbusProto::TcpUdp,
+            Transport::RtuSerial | Transport::RtuOverTcp => ModbusProto::Rtu,
+            Transport::AsciiSerial | Transport::AsciiOverTcp => ModbusProto::Ascii,
+        }
+    }
+}
+
+/// Everything needed to open a connection to a device. An open connection is
+/// dropped once these change, so edits in the device frame take effect.
+#[derive(PartialEq, Clone)]
+pub struct LinkSettings {
+    pub transport: Transport,
+    pub ip: String,
+    pub port: String,
+    pub serial: SerialSettings,
+    pub tls: crate::tls::TlsSettings,
+    pub timing: TimingSettings,
+    /// Modbus/TCP gateway to a serial line, it passes unit 0 on as a broadcast
+    pub gateway: bool,
+}
+
+impl LinkSettings {
+    /// Unit 0 is the broadcast address of a serial line. A Modbus/TCP server takes
+    /// it as its own address, unless it is a gateway handing it on to the line.
+    pub fn unit_0_is_broadcast(&self) -> bool {
+        self.transport.proto() != ModbusProto::TcpUdp || self.gateway
+    }
+}
+
+/// Time given to slaves to act on a broadcast before the next request
+const BROADCAST_TURNAROUND: Duration = Duration::from_millis(100);
+
+/// Longest ASCII line a response can be, ":" + 2 * (1 + 253 + 1) hex digits + CR LF
+const ASCII_MAX_LINE: usize = 513;
+
+enum Link {
+    Tcp(TcpStream),
+    /// Datagrams are resent on their own, the count is how many times
+    Udp(UdpSocket, u32),
+    #[cfg(not(target_arch = "wasm32"))]
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Serial(Box<dyn serialport::SerialPort>, Duration),
+}
+
+pub struct Connection {
+    link: Link,
+    proto: ModbusProto,
+    /// Requests to unit 0 reach every slave and are not answered
+    unit_0_is_broadcast: bool,
+    /// Request as built, before any ASCII encoding, to check the answer against
+    request: Vec<u8>,
+    transaction_id: u16,
+    /// Frames exactly as they went over the wire, kept for troubleshooting
+    pub last_request: Vec<u8>,
+    pub last_response: Vec<u8>,
+}
+
+impl Connection {
+    pub fn open(settings: &LinkSettings) -> Result<Self, std::io::Error> {
+        let connect_timeout = settings.timing.connect_timeout();
+        let timeout = settings.timing.response_timeout();
+        let (ip, port) = (&settings.ip, &settings.port);
+
+        let link = match settings.transport {
+            Transport::Tcp | Transport::RtuOverTcp | Transport::AsciiOverTcp => {
+                Link::Tcp(connect_tcp(ip, port, connect_timeout, timeout)?)
+            }
+            Transport::Udp => {
+                let udp_socket = UdpSocket::bind("0.0.0.0:0")?;
+                udp_socket.connect(format!("{}:{}", ip, port))?;
+                udp_socket.set_read_timeout(Some(timeout))?;
+                udp_socket.set_write_timeout(Some(timeout))?;
+                Link::Udp(udp_socket, settings.timing.retries)
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Transport::Tls => Link::Tls(Box::new(
+                settings
+                    .tls
+                    .connect(connect_tcp(ip, port, connect_timeout, timeout)?, ip)?,
+            )),
+            #[cfg(not(target_arch = "wasm32"))]
+            Transport::RtuSerial | Transport::AsciiSerial => {
+                let serial = &settings.serial;
+                let serial_port = serialport::new(serial.port_name.as_str(), serial.baud_rate)
+                    .data_bits(match serial.data_bits {
+                        7 => serialport::DataBits::Seven,
+                        _ => serialport::DataBits::Eight,
+                    })
+                    .parity(match serial.parity {
+                        Parity::None => serialport::Parity::None,
+                        Parity::Odd => serialport::Parity::Odd,
+                        Parity::Even => serialport::Parity::Even,
+                    })
+                    .stop_bits(match serial.stop_bits {
+                        StopBits::One => serialport::StopBits::One,
+                        StopBits::Two => serialport::StopBits::Two,
+                    })
+                    .timeout(timeout)
+                    .open()?;
+                Link::Serial(serial_port, serial.frame_delay())
+            }
+            #[cfg(target_arch = "wasm32")]
+            Transport::RtuSerial | Transport::AsciiSerial | Transport::Tls => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "Serial ports and TLS are not available in the browser",
+                ))
+            }
+        };
+
+        Ok(Self {
+            link,
+            proto: settings.transport.proto(),
+            unit_0_is_broadcast: settings.unit_0_is_broadcast(),
+            request: vec![],
+            transaction_id: 0,
+            last_request: vec![],
+            last_response: vec![],
+        })
+    }
+
+    /// Sends a request made by rmodbus, ASCII requests are hex encoded here.
+    /// MBAP requests get the next transaction ID of this connection.
+    pub fn send(&mut self, request: &[u8]) -> Result<(), std::io::Error> {
+        self.last_response.clear();
+        self.last_request.clear();
+        self.request = request.to_vec();
+        if self.proto == ModbusProto::TcpUdp && self.request.len() >= 2 {
+            self.transaction_id = self.transaction_id.wrapping_add(1);
+            self.request[0..2].copy_from_slice(&self.transaction_id.to_be_bytes());
+        }
+        match self.proto {
+            ModbusProto::Ascii => {
+                generate_ascii_frame(&self.request, &mut self.last_request).map_err(invalid_data)?
+            }
+            _ => self.last_request.extend_from_slice(&self.request),
+        }
+
+        match &mut self.link {
+            Link::Tcp(con) => {
+                // Only one request is out at a time, anything waiting now is a late answer,
+                // like one to a broadcast from a server that answers unit 0 itself
+                con.set_nonblocking(true)?;
+                while con.read(&mut [0u8; 260]).map_or(false, |len| len > 0) {}
+                con.set_nonblocking(false)?;
+                con.write_all(&self.last_request)
+            }
+            Link::Udp(con, _) => {
+                // Throw away late and duplicated answers to earlier requests
+                con.set_nonblocking(true)?;
+                while con.recv(&mut [0u8; 260]).is_ok() {}
+                con.set_nonblocking(false)?;
+                con.send(&self.last_request)?;
+                Ok(())
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Link::Tls(con) => {
+                con.write_all(&self.last_request)?;
+                con.flush()
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Link::Serial(con, frame_delay) => {
+                // Keep the bus silent long enough for the slaves to see a new frame,
+                // and drop any leftovers of earlier, late answers
+                std::thread::sleep(*frame_delay);
+                con.clear(serialport::ClearBuffer::Input)?;
+                con.write_all(&self.last_request)?;
+                con.flush()
+            }
+        }
+    }
+
+    /// Sends a request and waits for its response. Broadcasts are not answered,
+    /// so they only get the turnaround delay the slaves need to carry them out.
+    pub fn exchange(&mut self, request: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        self.send(request)?;
+        if self.is_broadcast() {
+            std::thread::sleep(BROADCAST_TURNAROUND);
+            return Ok(vec![]);
+        }
+        self.receive()
+    }
+
+    fn is_broadcast(&self) -> bool {
+        let unit = match self.proto {
+            ModbusProto::TcpUdp => 6,
+            _ => 0,
+        };
+        self.unit_0_is_broadcast && self.request.get(unit) == Some(&0)
+    }
+
+    /// Reads one complete response frame, header and checksum included.
+    /// ASCII responses are decoded back to binary so rmodbus can parse them.
+    pub fn receive(&mut self) -> Result<Vec<u8>, std::io::Error> {
+        match &mut self.link {
+            Link::Tcp(con) => receive_frame(con, self.proto, &mut self.last_response),
+            Link::Udp(con, retries) => receive_datagram(con, &self.last_request, *retries)
+                .map(|datagram| self.last_response = datagram),
+            #[cfg(not(target_arch = "wasm32"))]
+            Link::Tls(con) => receive_frame(con, self.proto, &mut self.last_response),
+            #[cfg(not(target_arch = "wasm32"))]
+            Link::Serial(con, _) => receive_frame(con, self.proto, &mut self.last_response),
+        }
+        .map_err(|e| match e.kind() {
+            // Sockets and serial ports word this differently, and not very clearly
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "No response within the response timeout",
+            ),
+            _ => e,
+        })?;
+
+        let response = match self.proto {
+            ModbusProto::Ascii => {
+                let mut frame = [0u8; 256];
+                let len =
+                    parse_ascii_frame(&self.last_response, self.last_response.len(), &mut frame, 0)
+                        .map_err(invalid_data)?;
+                frame[..len as usize].to_vec()
+            }
+            _ => self.last_response.clone(),
+        };
+        self.check_response(&response)?;
+        Ok(response)
+    }
+
+    /// Transaction ID of the last MBAP request
+    pub fn transaction_id(&self) -> u16 {
+        self.transaction_id
+    }
+
+    /// Makes sure the response answers the request that was sent, exception
+    /// responses included.
+    fn check_response(&self, response: &[u8]) -> Result<(), std::io::Error> {
+        let head = match self.proto {
+            ModbusProto::TcpUdp => 6,
+            _ => 0,
+        };
+        if self.request.len() < head + 2 || response.len() < head + 2 {
+            return Err(invalid_data(rmodbus::ErrorKind::FrameBroken));
+        }
+        // rmodbus checks these too, but only for the function codes it can parse
+        let (data, check) = match self.proto {
+            ModbusProto::Rtu => response.split_at(response.len() - 2),
+            ModbusProto::Ascii => response.split_at(response.len() - 1),
+            ModbusProto::TcpUdp => (response, &[][..]),
+        };
+        let checksum_ok = match self.proto {
+            ModbusProto::Rtu => crc16(data).to_le_bytes() == check,
+            ModbusProto::Ascii => [lrc(data)] == check,
+            ModbusProto::TcpUdp => true,
+        };
+        if !checksum_ok {
+            return Err(invalid_data(rmodbus::ErrorKind::FrameCRCError));
+        }
+
+        let mismatch = if head > 0 && response[0..2] != self.request[0..2] {
+            Some(ResponseMismatch::TransactionId {
+                sent: u16::from_be_bytes([self.request[0], self.request[1]]),
+                received: u16::from_be_bytes([response[0], response[1]]),
+            })
+        } else if response[head] != self.request[head] {
+            Some(ResponseMismatch::UnitId {
+                sent: self.request[head],
+                received: response[head],
+            })
+        } else if response[head + 1] & 0x7F != self.request[head + 1] {
+            Some(ResponseMismatch::FunctionCode {
+                sent: self.request[head + 1],
+                received: response[head + 1] & 0x7F,
+            })
+        } else {
+            None
+        };
+        match mismatch {
+            Some(mismatch) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                mismatch,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn request_text(&self) -> String {
+        self.frame_text(&self.last_request)
+    }
+
+    pub fn response_text(&self) -> String {
+        self.frame_text(&self.last_response)
+    }
+
+    /// ASCII frames are shown as the line itself, binary frames as hex bytes
+    fn frame_text(&self, frame: &[u8]) -> String {
+        match self.proto {
+            ModbusProto::Ascii => String::from_utf8_lossy(frame).trim_end().to_owned(),
+            _ => frame
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+/// Frames a PDU the way rmodbus frames its requests, for the function codes
+/// it can't build. The MBAP transaction ID is left at 0, the connection sets it.
+pub fn frame_request(unit_id: u8, pdu: &[u8], proto: ModbusProto) -> Vec<u8> {
+    let mut frame = vec![];
+    if proto == ModbusProto::TcpUdp {
+        frame.extend_from_slice(&[0, 0, 0, 0]);
+        frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+    }
+    frame.push(unit_id);
+    frame.extend_from_slice(pdu);
+    match proto {
+        ModbusProto::Rtu => {
+            let crc = crc16(&frame);
+            frame.extend_from_slice(&crc.to_le_bytes());
+        }
+        ModbusProto::Ascii => frame.push(lrc(&frame)),
+        ModbusProto::TcpUdp => (),
+    }
+    frame
+}
+
+/// The PDU of a response, function code first, without header or checksum
+pub fn response_pdu(response: &[u8], proto: ModbusProto) -> &[u8] {
+    let (head, tail) = match proto {
+        ModbusProto::TcpUdp => (7, 0),
+        ModbusProto::Rtu => (1, 2),
+        ModbusProto::Ascii => (1, 1),
+    };
+    response
+        .get(head..response.len().saturating_sub(tail))
+        .unwrap_or_default()
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xA001
+            };
+        }
+    }
+    crc
+}
+
+fn lrc(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |lrc, x| lrc.wrapping_sub(*x))
+}
+
+/// Exception code carried by a response, `None` for a normal response
+pub fn exception_code(response: &[u8], proto: ModbusProto) -> Option<u8> {
+    let head = match proto {
+        ModbusProto::TcpUdp => 6,
+        _ => 0,
+    };
+    match response.get(head + 1) {
+        Some(func) if func & 0x80 != 0 => response.get(head + 2).copied(),
+        _ => None,
+    }
+}
+
+pub fn exception_text(code: u8) -> &'static str {
+    match code {
+        0x01 => "Illegal function",
+        0x02 => "Illegal data address",
+        0x03 => "Illegal data value",
+        0x04 => "Slave device failure",
+        0x05 => "Acknowledge",
+        0x06 => "Slave device busy",
+        0x07 => "Negative acknowledge",
+        0x08 => "Memory parity error",
+        0x0A => "Gateway path unavailable",
+        0x0B => "Gateway target device failed to respond",
+        _ => "Unknown exception",
+    }
+}
+
+/// A well formed response that does not belong to the request that was sent
+#[derive(Debug)]
+pub enum ResponseMismatch {
+    TransactionId { sent: u16, received: u16 },
+    UnitId { sent: u8, received: u8 },
+    FunctionCode { sent: u8, received: u8 },
+}
+
+impl std::fmt::Display for ResponseMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseMismatch::TransactionId { sent, received } => write!(
+                f,
+                "Transaction ID mismatch, sent {} but received {}",
+                sent, received
+            ),
+            ResponseMismatch::UnitId { sent, received } => write!(
+                f,
+                "Unit ID mismatch, sent {} but received {}",
+                sent, received
+            ),
+            ResponseMismatch::FunctionCode { sent, received } => write!(
+                f,
+                "Function code mismatch, sent {} but received {}",
+                sent, received
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResponseMismatch {}
+
+/// Reconnect attempts after a connection broke, before giving up
+const MAX_RECONNECTS: u32 = 5;
+
+/// Longest wait between two reconnect attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LinkState {
+    Disconnected,
+    Connected,
+    Reconnecting,
+    Failed,
+}
+
+/// Snapshot of a connection's health, small enough to hand to the UI
+#[derive(Clone)]
+pub struct LinkStatus {
+    pub state: LinkState,
+    pub last_error: String,
+    failures: u32,
+    retry_at: Instant,
+}
+
+impl Default for LinkStatus {
+    fn default() -> Self {
+        Self {
+            state: LinkState::Disconnected,
+            last_error: Default::default(),
+            failures: 0,
+            retry_at: Instant::now(),
+        }
+    }
+}
+
+impl LinkStatus {
+    /// Time left until the next reconnect attempt, if one is planned
+    pub fn reconnect_in(&self) -> Option<Duration> {
+        match self.state {
+            LinkState::Reconnecting => {
+                Some(self.retry_at.saturating_duration_since(Instant::now()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn status_text(&self) -> String {
+        match self.state {
+            LinkState::Disconnected => "Not connected".to_owned(),
+            LinkState::Connected => "Connected".to_owned(),
+            LinkState::Reconnecting => format!(
+                "Reconnecting in {} s, attempt {} of {}\n{}",
+                self.retry_at
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f32()
+                    .ceil(),
+                self.failures,
+                MAX_RECONNECTS,
+                self.last_error
+            ),
+            LinkState::Failed => format!("Connection failed\n{}", self.last_error),
+        }
+    }
+}
+
+/// Keeps one connection per device open and shared by all of its queries.
+/// A broken connection is reopened in the background with a growing delay,
+/// while a request from the user always tries to connect right away.
+#[derive(Default)]
+pub struct ConnectionManager {
+    connection: Option<Connection>,
+    settings: Option<LinkSettings>,
+    status: LinkStatus,
+}
+
+impl ConnectionManager {
+    pub fn status(&self) -> &LinkStatus {
+        &self.status
+    }
+
+    /// Open connection for `settings`, connecting first if there is none
+    pub fn connection(
+        &mut self,
+        settings: &LinkSettings,
+    ) -> Result<&mut Connection, std::io::Error> {
+        if self.settings.as_ref() != Some(settings) {
+            self.close();
+            self.settings = Some(settings.clone());
+        }
+        if self.connection.is_none() {
+            self.connect(settings)?;
+        }
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    /// Drops a connection that failed a request, it is reopened after a delay
+    pub fn failed(&mut self, e: &std::io::Error) {
+        self.connection = None;
+        let status = &mut self.status;
+        status.failures += 1;
+        status.last_error = e.to_string();
+        status.state = if status.failures > MAX_RECONNECTS {
+            LinkState::Failed
+        } else {
+            LinkState::Reconnecting
+        };
+        // Doubles from one second for every failure in a row
+        status.retry_at = Instant::now()
+            + Duration::from_secs(1 << status.failures.saturating_sub(1).min(5)).min(MAX_BACKOFF);
+    }
+
+    pub fn close(&mut self) {
+        self.connection = None;
+        self.status = Default::default();
+    }
+
+    /// Makes the planned reconnect attempt once its delay has passed
+    pub fn reconnect(&mut self) {
+        if self.status.reconnect_in() != Some(Duration::ZERO) {
+            return;
+        }
+        if let Some(settings) = self.settings.clone() {
+            let _ = self.connect(&settings);
+        }
+    }
+
+    fn connect(&mut self, settings: &LinkSettings) -> Result<(), std::io::Error> {
+        match Connection::open(settings) {
+            Ok(con) => {
+                self.connection = Some(con);
+                self.status = LinkStatus {
+                    state: LinkState::Connected,
+                    ..Default::default()
+                };
+                Ok(())
+            }
+            Err(e) => {
+                self.failed(&e);
+                Err(e)
+            }
+        }
+    }
+}
+
+fn invalid_data(e: rmodbus::ErrorKind) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+/// Connects with a timeout, so an unreachable address doesn't hang the app
+/// for as long as the operating system is willing to wait.
+fn connect_tcp(
+    ip: &str,
+    port: &str,
+    connect_timeout: Duration,
+    timeout: Duration,
+) -> Result<TcpStream, std::io::Error> {
+    let mut last_error = std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{}:{} did not resolve to an address", ip, port),
+    );
+    for addr in format!("{}:{}", ip, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, connect_timeout) {
+            Ok(tcp_stream) => {
+                tcp_stream.set_read_timeout(Some(timeout))?;
+                tcp_stream.set_write_timeout(Some(timeout))?;
+                return Ok(tcp_stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Reads one response frame from a byte stream into `frame`, so whatever
+/// arrived is still there for troubleshooting if the read fails halfway.
+fn receive_frame(
+    con: &mut dyn Read,
+    proto: ModbusProto,
+    frame: &mut Vec<u8>,
+) -> Result<(), std::io::Error> {
+    if proto == ModbusProto::Ascii {
+        let mut byte = [0u8; 1];
+        loop {
+            con.read_exact(&mut byte)?;
+            // Anything before the start colon is line noise
+            if frame.is_empty() && byte[0] != b':' {
+                continue;
+            }
+            frame.push(byte[0]);
+            if byte[0] == b'\n' {
+                return Ok(());
+            }
+            if frame.len() > ASCII_MAX_LINE {
+                return Err(invalid_data(rmodbus::ErrorKind::FrameBroken));
+            }
+        }
+    }
+
+    // read the first bytes of the response frame, enough to guess the frame length
+    let mut head = vec![
+        0u8;
+        match proto {
+            ModbusProto::Rtu => 3,
+            _ => 6,
+        }
+    ];
+    con.read_exact(&mut head)?;
+    frame.extend_from_slice(&head);
+
+    loop {
+        let len = match proto {
+            ModbusProto::Rtu => match rtu_response_len(frame) {
+                Some(len) => len,
+                // Vendor function codes end where the checksum first fits
+                None if frame.len() >= 5 => {
+                    let (data, crc) = frame.split_at(frame.len() - 2);
+                    match crc16(data).to_le_bytes() == crc {
+                        true => frame.len(),
+                        false => frame.len() + 1,
+                    }
+                }
+                None => frame.len() + 1,
+            },
+            _ => guess_response_frame_len(frame, proto).map_err(invalid_data)? as usize,
+        };
+        if len <= frame.len() {
+            return Ok(());
+        }
+        if len > 256 {
+            return Err(invalid_data(rmodbus::ErrorKind::FrameBroken));
+        }
+        let mut rest = vec![0u8; len - frame.len()];
+        con.read_exact(&mut rest)?;
+        frame.extend(rest);
+    }
+}
+
+/// Length of an RTU response as far as the bytes read so far tell. It grows
+/// for responses that give their length further in, so read up to it and ask again.
+/// `None` for function codes that don't tell their length.
+fn rtu_response_len(frame: &[u8]) -> Option<usize> {
+    let pdu = &frame[1..];
+    let pdu_len = match pdu[0] {
+        func if func & 0x80 != 0 => 2,
+        1..=4 | 12 | 17 | 20 | 21 | 23 => 2 + pdu[1] as usize,
+        5 | 6 | 8 | 11 | 15 | 16 => 5,
+        7 => 2,
+        22 => 7,
+        24 => match pdu.get(2) {
+            Some(low) => 3 + u16::from_be_bytes([pdu[1], *low]) as usize,
+            None => 4,
+        },
+        // Read Device Identification lists its objects one after the other
+        43 => {
+            let mut len = 7;
+            if let Some(objects) = pdu.get(6) {
+                for _ in 0..*objects {
+                    match pdu.get(len + 1) {
+                        Some(object_len) => len += 2 + *object_len as usize,
+                        None => return Some(1 + len + 2 + 2),
+                    }
+                }
+            }
+            len
+        }
+        _ => return None,
+    };
+    Some(1 + pdu_len + 2)
+}
+
+/// Waits for the datagram answering the last request, matched on transaction ID.
+/// Stray and duplicated datagrams are dropped, lost ones are asked for again.
+fn receive_datagram(
+    con: &UdpSocket,
+    request: &[u8],
+    max_retries: u32,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut buf = [0u8; 260];
+    let mut retries = 0;
+    loop {
+        match con.recv(&mut buf) {
+            Ok(len) => {
+                if len < 6 || request.len() < 2 || buf[0..2] != request[0..2] {
+                    continue;
+                }
+                match guess_response_frame_len(&buf[..len], ModbusProto::TcpUdp) {
+                    Ok(frame_len) if frame_len as usize == len => return Ok(buf[..len].to_vec()),
+                    Ok(_) => {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "Datagram length does not match MBAP header",
+                        ))
+                    }
+                    Err(e) => return Err(invalid_data(e)),
+                }
+            }
+            Err(e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                if retries == max_retries {
+                    return Err(e);
+                }
+                retries += 1;
+                con.send(request)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Connection that has sent `request`, its link is never used
+    fn sent(proto: ModbusProto, request: &[u8]) -> Connection {
+        Connection {
+            link: Link::Udp(UdpSocket::bind("127.0.0.1:0").unwrap(), 0),
+            proto,
+            unit_0_is_broadcast: false,
+            request: request.to_vec(),
+            transaction_id: 0,
+            last_request: vec![],
+            last_response: vec![],
+        }
+    }
+
+    fn mismatch(con: &Connection, response: &[u8]) -> ResponseMismatch {
+        let e = con.check_response(response).unwrap_err();
+        *e.into_inner()
+            .unwrap()
+            .downcast::<ResponseMismatch>()
+            .unwrap()
+    }
+
+    /// Transaction 7, unit 1 reads holding register 0
+    const READ_ONE: [u8; 12] = [0, 7, 0, 0, 0, 6, 1, 3, 0, 0, 0, 1];
+
+    #[test]
+    fn check_response_takes_the_answer() {
+        let con = sent(ModbusProto::TcpUdp, &READ_ONE);
+        assert!(con
+            .check_response(&[0, 7, 0, 0, 0, 5, 1, 3, 2, 0, 42])
+            .is_ok());
+        // An exception answers the request too
+        assert!(con.check_response(&[0, 7, 0, 0, 0, 3, 1, 0x83, 2]).is_ok());
+    }
+
+    #[test]
+    fn check_response_finds_other_transaction() {
+        let con = sent(ModbusProto::TcpUdp, &READ_ONE);
+        assert!(matches!(
+            mismatch(&con, &[0, 8, 0, 0, 0, 5, 1, 3, 2, 0, 42]),
+            ResponseMismatch::TransactionId {
+                sent: 7,
+                received: 8
+            }
+        ));
+    }
+
+    #[test]
+    fn check_response_finds_other_unit() {
+        let con = sent(ModbusProto::TcpUdp, &READ_ONE);
+        let x = mismatch(&con, &[0, 7, 0, 0, 0, 5, 2, 3, 2, 0, 42]);
+        assert!(matches!(
+            x,
+            ResponseMismatch::UnitId {
+                sent: 1,
+                received: 2
+            }
+        ));
+        assert_eq!(x.to_string(), "Unit ID mismatch, sent 1 but received 2");
+    }
+
+    #[test]
+    fn check_response_finds_other_function_code() {
+        let con = sent(ModbusProto::TcpUdp, &READ_ONE);
+        assert!(matches!(
+            mismatch(&con, &[0, 7, 0, 0, 0, 5, 1, 4, 2, 0, 42]),
+            ResponseMismatch::FunctionCode {
+                sent: 3,
+                received: 4
+            }
+        ));
+        assert!(matches!(
+            mismatch(&con, &[0, 7, 0, 0, 0, 3, 1, 0x84, 2]),
+            ResponseMismatch::FunctionCode {
+                sent: 3,
+                received: 4
+            }
+        ));
+    }
+
+    #[test]
+    fn check_response_rejects_short_frames() {
+        let con = sent(ModbusProto::TcpUdp, &READ_ONE);
+        let e = con.check_response(&[0, 7, 0, 0, 0, 1, 1]).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn crc16_matches_known_frame() {
+        // Read 10 holding registers of unit 1 from 0, as sent on the wire: ... C5 CD
+        assert_eq!(
+            crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]).to_le_bytes(),
+            [0xC5, 0xCD]
+        );
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn lrc_matches_known_frame() {
+        // ":010300000001FB"
+        assert_eq!(lrc(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]), 0xFB);
+        assert_eq!(lrc(&[]), 0x00);
+    }
+
+    #[test]
+    fn frame_request_appends_checksums() {
+        let pdu = [0x03, 0x00, 0x00, 0x00, 0x0A];
+        assert_eq!(
+            frame_request(1, &pdu, ModbusProto::Rtu),
+            [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD]
+        );
+        assert_eq!(
+            frame_request(1, &pdu, ModbusProto::Ascii),
+            [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xF2]
+        );
+        assert_eq!(
+            frame_request(1, &pdu, ModbusProto::TcpUdp),
+            [0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]
+        );
+    }
+
+    #[test]
+    fn rtu_response_len_of_register_reads() {
+        // Unit, function, byte count, then two registers
+        assert_eq!(rtu_response_len(&[0x01, 0x03, 0x04]), Some(9));
+        assert_eq!(rtu_response_len(&[0x01, 0x03, 0x00]), Some(5));
+    }
+
+    #[test]
+    fn rtu_response_len_of_register_writes() {
+        assert_eq!(rtu_response_len(&[0x01, 0x10, 0x00]), Some(8));
+        assert_eq!(rtu_response_len(&[0x01, 0x06, 0x00]), Some(8));
+    }
+
+    #[test]
+    fn rtu_response_len_of_exceptions() {
+        assert_eq!(rtu_response_len(&[0x01, 0x83, 0x02]), Some(5));
+        assert_eq!(rtu_response_len(&[0x01, 0xAB, 0x01]), Some(5));
+    }
+
+    #[test]
+    fn rtu_response_len_of_device_identification() {
+        let header = [0x01, 0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00, 0x02];
+        // Until all objects are in, asks for the header of the next one
+        assert_eq!(rtu_response_len(&header), Some(12));
+        let mut frame = header.to_vec();
+        frame.extend_from_slice(&[0x00, 0x03, b'a', b'b', b'c']);
+        assert_eq!(rtu_response_len(&frame), Some(17));
+        frame.extend_from_slice(&[0x01, 0x02, b'x', b'y']);
+        assert_eq!(rtu_response_len(&frame), Some(19));
+    }
+
+    #[test]
+    fn rtu_response_len_of_unknown_codes() {
+        assert_eq!(rtu_response_len(&[0x01, 0x41, 0x00]), None);
+    }
+
+    fn link(transport: Transport, gateway: bool) -> LinkSettings {
+        LinkSettings {
+            transport,
+            ip: Default::default(),
+            port: Default::default(),
+            serial: Default::default(),
+            tls: Default::default(),
+            timing: Default::default(),
+            gateway,
+        }
+    }
+
+    #[test]
+    fn unit_0_is_broadcast_on_serial_lines_and_through_gateways() {
+        assert!(!link(Transport::Tcp, false).unit_0_is_broadcast());
+        assert!(!link(Transport::Tls, false).unit_0_is_broadcast());
+        assert!(link(Transport::Udp, true).unit_0_is_broadcast());
+        assert!(link(Transport::RtuOverTcp, false).unit_0_is_broadcast());
+        assert!(link(Transport::AsciiSerial, false).unit_0_is_broadcast());
+    }
+}
@@ -1,3 +1,26 @@
+/// Name of one value of a watched register
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct NamedState {
+    pub value: i128,
+    pub name: String,
+}
+
+/// Bits of a watched register shown by name, a flag when one bit wide
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct NamedBits {
+    pub bit: u8,
+    pub width: u8,
+    pub name: String,
+}
+
+/// Shows the raw value of an integer register by name instead of scaled
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub enum ValueMapping {
+    None,
+    States(Vec<NamedState>),
+    Bitfield(Vec<NamedBits>),
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct WatchedReg {
@@ -6,9 +29,18 @@ pub struct WatchedReg {
     pub pos: usize,
     pub factor: f32,
     pub value_offsett: f32,
-    pub resulting_value: f32,
+    pub resulting_value: f64,
     pub locked: bool,
     pub data_type: crate::query::DataView,
+    pub endianness: crate::query::Endianness,
+    /// Registers read by the text view
+    pub text: crate::text::TextView,
+    #[serde(skip)]
+    pub resulting_text: String,
+    pub mapping: ValueMapping,
+    /// Integer as read, before factor and offset. Wide enough for both U64 and I64.
+    #[serde(skip)]
+    raw_value: Option<i128>,
 }
 
 impl Default for WatchedReg {
@@ -22,6 +54,11 @@ impl Default for WatchedReg {
             resulting_value: 0.,
             locked: false,
             data_type: crate::query::DataView::Unsigned16bit,
+            endianness: crate::query::Endianness::Cdab,
+            text: Default::default(),
+            resulting_text: Default::default(),
+            mapping: ValueMapping::None,
+            raw_value: None,
         }
     }
 }
@@ -34,6 +71,7 @@ impl WatchedReg {
         factor: f32,
         value_offsett: f32,
         typ: crate::query::DataView,
+        endianness: crate::query::Endianness,
     ) -> Self {
         Self {
             label,
@@ -44,53 +82,326 @@ impl WatchedReg {
             resulting_value: 0.,
             locked: false,
             data_type: typ,
+            endianness,
+            text: Default::default(),
+            resulting_text: Default::default(),
+            mapping: ValueMapping::None,
+            raw_value: None,
         }
     }
 
     pub fn update(&mut self, read_bytes: &[u8]) {
-        match self.data_type {
+        self.raw_value = match self.data_type {
+            crate::query::DataView::Unsigned16bit => self
+                .bytes(read_bytes)
+                .map(|x| u16::from_be_bytes(x) as i128),
+            crate::query::DataView::Signed16bit => self
+                .bytes(read_bytes)
+                .map(|x| i16::from_be_bytes(x) as i128),
+            crate::query::DataView::Unsigned32bit => self
+                .bytes(read_bytes)
+                .map(|x| u32::from_be_bytes(x) as i128),
+            crate::query::DataView::Signed32bit => self
+                .bytes(read_bytes)
+                .map(|x| i32::from_be_bytes(x) as i128),
+            crate::query::DataView::Unsigned64bit => self
+                .bytes(read_bytes)
+                .map(|x| u64::from_be_bytes(x) as i128),
+            crate::query::DataView::Signed64bit => self
+                .bytes(read_bytes)
+                .map(|x| i64::from_be_bytes(x) as i128),
+            _ => None,
+        };
+        let value = match self.data_type {
             crate::query::DataView::Unsigned16bit => {
-                self.resulting_value =
-                    u16::from_be_bytes([read_bytes[self.pos], read_bytes[self.pos + 1]]) as f32
-                        * self.factor
-                        + self.value_offsett
+                self.bytes(read_bytes).map(|x| u16::from_be_bytes(x) as f64)
             }
             crate::query::DataView::Signed16bit => {
-                self.resulting_value =
-                    i16::from_be_bytes([read_bytes[self.pos], read_bytes[self.pos + 1]]) as f32
-                        * self.factor
-                        + self.value_offsett
+                self.bytes(read_bytes).map(|x| i16::from_be_bytes(x) as f64)
             }
             crate::query::DataView::Unsigned32bit => {
-                self.resulting_value = u32::from_be_bytes([
-                    read_bytes[self.pos + 2],
-                    read_bytes[self.pos + 3],
-                    read_bytes[self.pos],
-                    read_bytes[self.pos + 1],
-                ]) as f32
-                    * self.factor
-                    + self.value_offsett
+                self.bytes(read_bytes).map(|x| u32::from_be_bytes(x) as f64)
             }
             crate::query::DataView::Signed32bit => {
-                self.resulting_value = i32::from_be_bytes([
-                    read_bytes[self.pos + 2],
-                    read_bytes[self.pos + 3],
-                    read_bytes[self.pos],
-                    read_bytes[self.pos + 1],
-                ]) as f32
-                    * self.factor
-                    + self.value_offsett
+                self.bytes(read_bytes).map(|x| i32::from_be_bytes(x) as f64)
             }
             crate::query::DataView::Float32bit => {
-                self.resulting_value = f32::from_be_bytes([
-                    read_bytes[self.pos + 2],
-                    read_bytes[self.pos + 3],
-                    read_bytes[self.pos],
-                    read_bytes[self.pos + 1],
-                ]) * self.factor
-                    + self.value_offsett
+                self.bytes(read_bytes).map(|x| f32::from_be_bytes(x) as f64)
+            }
+            crate::query::DataView::Unsigned64bit => {
+                self.bytes(read_bytes).map(|x| u64::from_be_bytes(x) as f64)
             }
-            crate::query::DataView::Hexadecimal => (),
+            crate::query::DataView::Signed64bit => {
+                self.bytes(read_bytes).map(|x| i64::from_be_bytes(x) as f64)
+            }
+            crate::query::DataView::Float64bit => self.bytes(read_bytes).map(f64::from_be_bytes),
+            crate::query::DataView::Hexadecimal | crate::query::DataView::Bits => None,
+            crate::query::DataView::Text => {
+                let span = self.text.span(read_bytes.len());
+                self.resulting_text = self.text.decode(&read_bytes[span]);
+                None
+            }
+        };
+        if let Some(value) = value {
+            self.resulting_value = value * self.factor as f64 + self.value_offsett as f64;
         }
     }
+
+    pub fn value_text(&self) -> String {
+        if let Some(text) = self.mapped_text() {
+            return text;
+        }
+        match (self.data_type, self.raw_value) {
+            (crate::query::DataView::Text, _) => self.resulting_text.clone(),
+            // Integers don't have more decimals than their factor and offset
+            (_, Some(raw)) => crate::query::scaled_text(raw, self.factor, self.value_offsett),
+            _ => self.resulting_value.to_string(),
+        }
+    }
+
+    /// Names of the raw value, `None` without a mapping or an integer read
+    fn mapped_text(&self) -> Option<String> {
+        let raw = self.raw_value?;
+        match &self.mapping {
+            ValueMapping::None => None,
+            ValueMapping::States(states) => Some(
+                states
+                    .iter()
+                    .find(|x| x.value == raw)
+                    .map_or_else(|| format!("Unknown {}", raw), |x| x.name.clone()),
+            ),
+            ValueMapping::Bitfield(fields) => {
+                let set = fields
+                    .iter()
+                    .filter_map(|x| {
+                        let mask = u64::MAX.checked_shr(64 - x.width as u32).unwrap_or(0);
+                        let value = (raw as u64).checked_shr(x.bit as u32).unwrap_or(0) & mask;
+                        match x.width {
+                            1 => (value != 0).then(|| x.name.clone()),
+                            _ => Some(format!("{} {}", x.name, value)),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Some(match set.is_empty() {
+                    true => "No flags".to_owned(),
+                    false => set.join(", "),
+                })
+            }
+        }
+    }
+
+    /// Editor of the named states or bits, they apply to integer data types
+    pub fn draw_mapping(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .radio(matches!(self.mapping, ValueMapping::None), "Value")
+                .clicked()
+            {
+                self.mapping = ValueMapping::None;
+            }
+            if ui
+                .radio(matches!(self.mapping, ValueMapping::States(_)), "States")
+                .clicked()
+                && !matches!(self.mapping, ValueMapping::States(_))
+            {
+                self.mapping = ValueMapping::States(vec![]);
+            }
+            if ui
+                .radio(matches!(self.mapping, ValueMapping::Bitfield(_)), "Bits")
+                .clicked()
+                && !matches!(self.mapping, ValueMapping::Bitfield(_))
+            {
+                self.mapping = ValueMapping::Bitfield(vec![]);
+            }
+        });
+
+        match &mut self.mapping {
+            ValueMapping::None => (),
+            ValueMapping::States(states) => {
+                egui::Grid::new(ui.id().with("watched_states"))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Value");
+                        ui.label("Name");
+                        ui.end_row();
+                        let mut row = 0;
+                        states.retain_mut(|x| {
+                            let id = ui.id().with(("watched_state", row));
+                            crate::query::integer_edit(ui, id, &mut x.value);
+                            row += 1;
+                            ui.text_edit_singleline(&mut x.name);
+                            let retain = !ui.button("\u{1F5D1}").clicked();
+                            ui.end_row();
+                            retain
+                        });
+                    });
+                if ui.button("Add State").clicked() {
+                    let value = states.last().map_or(0, |x| x.value.wrapping_add(1));
+                    states.push(NamedState {
+                        value,
+                        name: format!("State {}", value),
+                    });
+                }
+            }
+            ValueMapping::Bitfield(fields) => {
+                egui::Grid::new(ui.id().with("watched_bits"))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Bit");
+                        ui.label("Width");
+                        ui.label("Name");
+                        ui.end_row();
+                        fields.retain_mut(|x| {
+                            ui.add(
+                                egui::DragValue::new(&mut x.bit)
+                                    .clamp_range(0..=63)
+                                    .speed(0.0),
+                            )
+                            .on_hover_cursor(egui::CursorIcon::Text);
+                            ui.add(
+                                egui::DragValue::new(&mut x.width)
+                                    .clamp_range(1..=64 - x.bit)
+                                    .speed(0.0),
+                            )
+                            .on_hover_cursor(egui::CursorIcon::Text)
+                            .on_hover_text(
+                                "One bit shows the name when set, more show their value",
+                            );
+                            ui.text_edit_singleline(&mut x.name);
+                            let retain = !ui.button("\u{1F5D1}").clicked();
+                            ui.end_row();
+                            retain
+                        });
+                    });
+                if ui.button("Add Bits").clicked() {
+                    let bit = fields.last().map_or(0, |x| (x.bit + x.width).min(63));
+                    fields.push(NamedBits {
+                        bit,
+                        width: 1,
+                        name: format!("Bit {}", bit),
+                    });
+                }
+            }
+        }
+    }
+
+    /// The value most significant byte first, `None` while the read data is shorter
+    fn bytes<const N: usize>(&self, read_bytes: &[u8]) -> Option<[u8; N]> {
+        let bytes = read_bytes.get(self.pos..self.pos + N)?.try_into().ok()?;
+        Some(self.endianness.swap(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{DataView, Endianness};
+
+    fn watched(data_type: DataView, mapping: ValueMapping) -> WatchedReg {
+        WatchedReg {
+            data_type,
+            endianness: Endianness::Abcd,
+            mapping,
+            ..Default::default()
+        }
+    }
+
+    fn bits(bit: u8, width: u8, name: &str) -> NamedBits {
+        NamedBits {
+            bit,
+            width,
+            name: name.to_owned(),
+        }
+    }
+
+    fn state(value: i128, name: &str) -> NamedState {
+        NamedState {
+            value,
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn bitfield_names_set_flags_and_field_values() {
+        let mut x = watched(
+            DataView::Unsigned16bit,
+            ValueMapping::Bitfield(vec![
+                bits(0, 1, "Ready"),
+                bits(4, 3, "Mode"),
+                bits(15, 1, "Alarm"),
+            ]),
+        );
+        x.update(&[0x80, 0x51]);
+        assert_eq!(x.value_text(), "Ready, Mode 5, Alarm");
+        x.update(&[0x00, 0x00]);
+        assert_eq!(x.value_text(), "Mode 0");
+    }
+
+    #[test]
+    fn bitfield_without_set_flags() {
+        let mut x = watched(
+            DataView::Unsigned16bit,
+            ValueMapping::Bitfield(vec![bits(0, 1, "Ready")]),
+        );
+        x.update(&[0x00, 0x02]);
+        assert_eq!(x.value_text(), "No flags");
+    }
+
+    #[test]
+    fn bitfield_reads_top_bit_of_u64() {
+        let mut x = watched(
+            DataView::Unsigned64bit,
+            ValueMapping::Bitfield(vec![bits(63, 1, "Top"), bits(0, 1, "Bottom")]),
+        );
+        x.update(&[0x80, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(x.value_text(), "Top");
+    }
+
+    #[test]
+    fn states_name_the_raw_value() {
+        let mut x = watched(
+            DataView::Signed16bit,
+            ValueMapping::States(vec![state(-1, "Fault"), state(1, "Running")]),
+        );
+        x.update(&[0xFF, 0xFF]);
+        assert_eq!(x.value_text(), "Fault");
+        x.update(&[0x00, 0x02]);
+        assert_eq!(x.value_text(), "Unknown 2");
+    }
+
+    #[test]
+    fn no_mapping_before_a_read() {
+        let x = watched(
+            DataView::Unsigned16bit,
+            ValueMapping::States(vec![state(0, "Off")]),
+        );
+        assert_eq!(x.mapped_text(), None);
+    }
+
+    #[test]
+    fn states_keep_u64_above_i64_max_positive() {
+        let mut x = watched(
+            DataView::Unsigned64bit,
+            ValueMapping::States(vec![state(-1, "Fault")]),
+        );
+        x.update(&[0xFF; 8]);
+        assert_eq!(x.value_text(), "Unknown 18446744073709551615");
+        x.mapping = ValueMapping::States(vec![state(u64::MAX.into(), "Unset")]);
+        assert_eq!(x.value_text(), "Unset");
+    }
+
+    #[test]
+    fn states_above_i64_max_are_saved() {
+        let states = ValueMapping::States(vec![state(u64::MAX.into(), "Unset")]);
+        let saved = ron::to_string(&states).unwrap();
+        let ValueMapping::States(loaded) = ron::from_str(&saved).unwrap() else {
+            panic!("not states: {}", saved);
+        };
+        assert_eq!(loaded[0].value, u64::MAX.into());
+        let saved = serde_json::to_string(&states).unwrap();
+        let ValueMapping::States(loaded) = serde_json::from_str(&saved).unwrap() else {
+            panic!("not states: {}", saved);
+        };
+        assert_eq!(loaded[0].value, u64::MAX.into());
+    }
 }
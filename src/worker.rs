@@ -0,0 +1,237 @@
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::transport::{ConnectionManager, LinkSettings, LinkStatus};
+
+enum Command {
+    Request {
+        id: u64,
+        settings: Box<LinkSettings>,
+        request: Vec<u8>,
+    },
+    Close,
+}
+
+enum Event {
+    Reply(Reply),
+    Status(LinkStatus),
+}
+
+/// Outcome of one request, frames are kept as text for the raw frame view
+pub struct Reply {
+    pub id: u64,
+    pub response: Result<Vec<u8>, std::io::Error>,
+    /// Given by the connection, the request itself is built without one
+    pub transaction_id: u16,
+    pub raw_request: String,
+    pub raw_response: String,
+}
+
+/// Owns the device connection and does all the waiting on it, so a slow or
+/// dead device never holds up drawing.
+struct Backend {
+    manager: ConnectionManager,
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+    cancelled: Arc<Mutex<HashSet<u64>>>,
+    ctx: egui::Context,
+    last_done: Instant,
+}
+
+impl Backend {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn run(mut self) {
+        use std::sync::mpsc::RecvTimeoutError;
+        loop {
+            let command = match self.manager.status().reconnect_in() {
+                Some(wait) => self.commands.recv_timeout(wait),
+                None => self
+                    .commands
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match command {
+                Ok(command) => self.step(command),
+                Err(RecvTimeoutError::Timeout) => {
+                    self.manager.reconnect();
+                    self.publish(None);
+                }
+                // The device was removed
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    fn step(&mut self, command: Command) {
+        match command {
+            Command::Request {
+                id,
+                settings,
+                request,
+            } => {
+                if self.cancelled.lock().unwrap().remove(&id) {
+                    return;
+                }
+
+                let wait = settings
+                    .timing
+                    .request_gap()
+                    .saturating_sub(self.last_done.elapsed());
+                if !wait.is_zero() {
+                    std::thread::sleep(wait);
+                }
+
+                // UDP resends the datagram itself, so late answers still match
+                let retries = match settings.transport {
+                    crate::transport::Transport::Udp => 0,
+                    _ => settings.timing.retries,
+                };
+                let mut reply = self.attempt(id, &settings, &request);
+                for _ in 0..retries {
+                    if reply.response.is_ok() {
+                        break;
+                    }
+                    reply = self.attempt(id, &settings, &request);
+                }
+                self.last_done = Instant::now();
+
+                // Cancelled while on the wire, nobody waits for the reply any more
+                let cancelled = self.cancelled.lock().unwrap().remove(&id);
+                self.publish((!cancelled).then_some(reply));
+            }
+            Command::Close => {
+                self.manager.close();
+                self.publish(None);
+            }
+        }
+    }
+
+    fn attempt(&mut self, id: u64, settings: &LinkSettings, request: &[u8]) -> Reply {
+        match self.manager.connection(settings) {
+            Ok(con) => {
+                let response = con.exchange(request);
+                let reply = Reply {
+                    id,
+                    transaction_id: con.transaction_id(),
+                    raw_request: con.request_text(),
+                    raw_response: con.response_text(),
+                    response,
+                };
+                // A unit that doesn't answer says nothing about the link, other
+                // slaves behind the same gateway may be fine
+                if let Err(e) = &reply.response {
+                    if e.kind() != std::io::ErrorKind::TimedOut {
+                        self.manager.failed(e);
+                    }
+                }
+                reply
+            }
+            Err(e) => Reply {
+                id,
+                response: Err(e),
+                transaction_id: 0,
+                raw_request: Default::default(),
+                raw_response: Default::default(),
+            },
+        }
+    }
+
+    fn publish(&self, reply: Option<Reply>) {
+        if let Some(reply) = reply {
+            let _ = self.events.send(Event::Reply(reply));
+        }
+        let _ = self
+            .events
+            .send(Event::Status(self.manager.status().clone()));
+        self.ctx.request_repaint();
+    }
+}
+
+/// Handle to the background worker of one device. Requests are queued and
+/// answered in order, replies are picked up with `poll` every frame.
+pub struct Worker {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    cancelled: Arc<Mutex<HashSet<u64>>>,
+    status: LinkStatus,
+    next_id: u64,
+    /// Browsers have no threads, there the requests run while polling
+    #[cfg(target_arch = "wasm32")]
+    backend: Backend,
+}
+
+impl Worker {
+    pub fn start(ctx: egui::Context) -> Self {
+        let (commands, command_receiver) = channel();
+        let (event_sender, events) = channel();
+        let cancelled = Arc::new(Mutex::new(HashSet::new()));
+        let backend = Backend {
+            manager: Default::default(),
+            commands: command_receiver,
+            events: event_sender,
+            cancelled: cancelled.clone(),
+            ctx,
+            last_done: Instant::now(),
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(move || backend.run());
+
+        Self {
+            commands,
+            events,
+            cancelled,
+            status: Default::default(),
+            next_id: 0,
+            #[cfg(target_arch = "wasm32")]
+            backend,
+        }
+    }
+
+    /// Queues a request and returns the id its reply will carry
+    pub fn submit(&mut self, settings: LinkSettings, request: Vec<u8>) -> u64 {
+        self.next_id += 1;
+        let _ = self.commands.send(Command::Request {
+            id: self.next_id,
+            settings: Box::new(settings),
+            request,
+        });
+        self.next_id
+    }
+
+    /// Skips a queued request, one already on the wire finishes but its reply is dropped
+    pub fn cancel(&self, id: u64) {
+        self.cancelled.lock().unwrap().insert(id);
+    }
+
+    pub fn close(&self) {
+        let _ = self.commands.send(Command::Close);
+    }
+
+    pub fn status(&self) -> &LinkStatus {
+        &self.status
+    }
+
+    /// Replies that arrived since the last call
+    pub fn poll(&mut self) -> Vec<Reply> {
+        #[cfg(target_arch = "wasm32")]
+        while let Ok(command) = self.backend.commands.try_recv() {
+            self.backend.step(command);
+        }
+
+        let mut replies = vec![];
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Reply(reply) => {
+                    // Cancelled after it was sent back, before it was picked up here
+                    if !self.cancelled.lock().unwrap().remove(&reply.id) {
+                        replies.push(reply);
+                    }
+                }
+                Event::Status(status) => self.status = status,
+            }
+        }
+        replies
+    }
+}